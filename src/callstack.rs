@@ -1,24 +1,35 @@
+use error::InkError;
+use json_parser::{read_array, read_int};
 use runtime::container::Container;
 use runtime::divert::PushPopType;
 use runtime::RuntimeObject;
+use runtime::value::Value;
 use runtime_context::RuntimeContext;
-use macros;
 
-use std::collections::HashMap;
+use serde_json;
+use serde_json::Map;
+
 use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Thread {
-    stack: Vec<RuntimeContext>
+    stack: Vec<RuntimeContext>,
+    /// Unique index of the thread, used to find the thread of a choice when loading a state.
+    index: usize
 }
 
-impl<'ru> Thread {
+impl Thread {
     pub fn new() -> Thread {
         Thread {
-            stack: Vec::new()
+            stack: Vec::new(),
+            index: 0
         }
     }
 
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
     pub fn stack(&self) -> &Vec<RuntimeContext> {
         &self.stack
     }
@@ -33,7 +44,7 @@ impl<'ru> Thread {
 
     pub fn pop_if<F>(&mut self, f: F) -> Option<RuntimeContext>
         where F: FnOnce(&RuntimeContext) -> bool {
-        let mut should_pop = match self.stack.last() {
+        let should_pop = match self.stack.last() {
             Some(runtime_context) => f(runtime_context),
             _ => false
         };
@@ -47,14 +58,32 @@ impl<'ru> Thread {
         None
     }
 
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = Map::new();
+        json.insert("callstack".to_owned(), serde_json::Value::Array(self.stack.iter().map(|runtime_context| runtime_context.to_json()).collect()));
+        json.insert("threadIndex".to_owned(), serde_json::Value::Number(self.index.into()));
+        serde_json::Value::Object(json)
+    }
+
+    pub fn from_json(json: &serde_json::Value, root_container: &Rc<Container>) -> Result<Thread, InkError> {
+        let mut thread = Thread::new();
+
+        for runtime_context in read_array(json, "callstack")? {
+            thread.push(RuntimeContext::from_json(runtime_context, root_container)?);
+        }
+
+        thread.index = read_int(json, "threadIndex")? as usize;
+        Ok(thread)
+    }
 }
 
 #[derive(Clone)]
 pub struct CallStack {
-    threads: Vec<Thread>
+    threads: Vec<Thread>,
+    thread_counter: usize
 }
 
-impl<'ru> CallStack {
+impl CallStack {
     pub fn new(root_container: &Rc<Container>) -> CallStack {
         let mut threads = Vec::new();
         let mut thread = Thread::new();
@@ -63,7 +92,8 @@ impl<'ru> CallStack {
         threads.push(thread);
 
         CallStack {
-            threads: threads
+            threads: threads,
+            thread_counter: 0
         }
     }
 
@@ -79,6 +109,10 @@ impl<'ru> CallStack {
         self.stack().and_then(|stack| stack.last())
     }
 
+    pub fn runtime_context_mut(&mut self) -> Option<&mut RuntimeContext> {
+        self.threads.last_mut().and_then(|thread| thread.stack.last_mut())
+    }
+
     pub fn runtime_object(&self) -> Option<&RuntimeObject> {
         match self.runtime_context() {
             Some(runtime_context) => runtime_context.get(),
@@ -93,28 +127,193 @@ impl<'ru> CallStack {
         }
     }
 
+    /// Push a new element for a tunnel or a function call, starting at the current content
+    /// pointer. The length of the output stream is kept to trim the output of functions.
+    pub fn push(&mut self, stack_push_type: PushPopType, output_stream_length: usize) -> Result<(), InkError> {
+        let thread = match self.threads.last_mut() {
+            Some(thread) => thread,
+            _ => return Err(InkError::from_message("Cannot push to the callstack without a thread"))
+        };
+
+        let runtime_context = match thread.stack.last() {
+            Some(runtime_context) => runtime_context.fork(stack_push_type, output_stream_length),
+            _ => return Err(InkError::from_message("Cannot push to an empty callstack"))
+        };
+
+        thread.push(runtime_context);
+        Ok(())
+    }
+
+    /// Whether the current element can be popped: it must have been pushed with the given type,
+    /// and the base element of the thread can never be popped.
+    pub fn can_pop(&self, stack_push_type: PushPopType) -> bool {
+        self.depth() > 1 && self.current_stack_push_type() == Some(stack_push_type)
+    }
+
+    pub fn pop(&mut self) -> Option<RuntimeContext> {
+        if self.depth() <= 1 {
+            return None;
+        }
+
+        self.threads.last_mut().and_then(|thread| thread.pop())
+    }
+
+    pub fn current_stack_push_type(&self) -> Option<PushPopType> {
+        self.runtime_context().map(|runtime_context| runtime_context.stack_push_type())
+    }
+
+    /// Some text has been output, there is no more whitespace to trim at the start of the
+    /// functions being evaluated.
+    pub fn clear_function_start_in_output_stream(&mut self) {
+        if let Some(thread) = self.threads.last_mut() {
+            for runtime_context in thread.stack.iter_mut().rev() {
+                if runtime_context.stack_push_type() != PushPopType::Function {
+                    break;
+                }
+
+                runtime_context.set_function_start_in_output_stream(None);
+            }
+        }
+    }
+
+    /// Get the context index of a variable: 0 for a global variable, or the 1-based index of the
+    /// current callstack element if the variable is a temporary declared there.
+    pub fn context_for_variable_named(&self, name: &str) -> i32 {
+        match self.runtime_context() {
+            Some(runtime_context) if runtime_context.temporary_variables().contains_key(name) => self.depth() as i32,
+            _ => 0
+        }
+    }
+
+    /// Get a temporary variable from the callstack element at the given 1-based context index,
+    /// or from the current element if the context index is -1.
+    pub fn temporary_variable(&self, name: &str, context_index: i32) -> Option<&Value> {
+        let stack = try_opt!(self.stack());
+        let index = if context_index == -1 { stack.len() } else { context_index as usize };
+
+        if index == 0 {
+            return None;
+        }
+
+        stack.get(index - 1).and_then(|runtime_context| runtime_context.temporary_variables().get(name))
+    }
+
+    /// Set a temporary variable in the callstack element at the given 1-based context index, or
+    /// in the current element if the context index is -1. Unless it is declared, the variable
+    /// must already exist.
+    pub fn set_temporary_variable(&mut self, name: &str, value: Value, declare_new: bool, context_index: i32) -> Result<(), InkError> {
+        let stack = match self.threads.last_mut() {
+            Some(thread) => &mut thread.stack,
+            _ => return Err(InkError::from_message("Cannot set a temporary variable without a callstack"))
+        };
+
+        let index = if context_index == -1 { stack.len() } else { context_index as usize };
+        let temporary_variables = match index.checked_sub(1).and_then(|index| stack.get_mut(index)) {
+            Some(runtime_context) => runtime_context.temporary_variables_mut(),
+            _ => return Err(InkError::from_message(format!("Invalid context index {} for temporary variable {}", context_index, name)))
+        };
+
+        let mut value = value;
+        match temporary_variables.get(name) {
+            Some(old_value) => value.retain_list_origins_for_assignment(old_value),
+            _ if !declare_new => return Err(InkError::from_message(format!("Could not find temporary variable to set: {}", name))),
+            _ => {}
+        }
+
+        temporary_variables.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    /// Replace the threads by a single thread, for instance by the thread captured by a choice.
+    pub fn set_thread(&mut self, thread: Thread) {
+        self.threads.clear();
+        self.threads.push(thread);
+    }
+
     pub fn thread_from_index(&self, index: usize) -> Option<&Thread> {
         self.threads.get(index)
     }
 
+    /// Get the active thread with the given unique index, see `Thread::index`.
+    pub fn thread_with_index(&self, index: usize) -> Option<&Thread> {
+        self.threads.iter().find(|thread| thread.index == index)
+    }
+
     pub fn push_thread(&mut self) -> bool {
-        if let Some(thread) = self.threads.last().map(|thread| thread.clone()) {
-            self.threads.push(thread);
-            return true;
+        match self.fork_thread() {
+            Some(thread) => {
+                self.threads.push(thread);
+                true
+            },
+            _ => false
         }
+    }
+
+    /// Get a copy of the current thread with a new unique index, e.g. for a choice to resume
+    /// from it.
+    pub fn fork_thread(&mut self) -> Option<Thread> {
+        let mut thread = try_opt!(self.threads.last().cloned());
 
-        false
+        self.thread_counter += 1;
+        thread.index = self.thread_counter;
+        Some(thread)
+    }
+
+    /// Whether the current thread was started from another thread, and can be popped to return
+    /// to it.
+    pub fn can_pop_thread(&self) -> bool {
+        self.threads.len() > 1
     }
 
     pub fn pop_thread(&mut self) -> bool {
-        match self.threads.pop() {
-            Some(_) => true,
-            _ => false
+        if !self.can_pop_thread() {
+            return false;
         }
+
+        self.threads.pop().is_some()
     }
 
     pub fn reset(&mut self, thread: Thread) {
         self.threads.clear();
         self.threads.push(thread);
     }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = Map::new();
+        json.insert("threads".to_owned(), serde_json::Value::Array(self.threads.iter().map(|thread| thread.to_json()).collect()));
+        json.insert("threadCounter".to_owned(), serde_json::Value::Number(self.thread_counter.into()));
+        serde_json::Value::Object(json)
+    }
+
+    pub fn from_json(json: &serde_json::Value, root_container: &Rc<Container>) -> Result<CallStack, InkError> {
+        let mut threads = Vec::new();
+
+        for thread in read_array(json, "threads")? {
+            threads.push(Thread::from_json(thread, root_container)?);
+        }
+
+        if threads.is_empty() {
+            return Err(InkError::from_message("Expected at least one thread in the story state"));
+        }
+
+        Ok(CallStack {
+            threads: threads,
+            thread_counter: read_int(json, "threadCounter")? as usize
+        })
+    }
+
+    /// Unwind all the threads and the callstack. The base element of the first thread is kept,
+    /// pointing to nothing, so that new elements can still be pushed on top of it.
+    pub fn force_end(&mut self) {
+        self.threads.truncate(1);
+
+        if let Some(thread) = self.threads.first_mut() {
+            thread.stack.truncate(1);
+
+            if let Some(runtime_context) = thread.stack.first_mut() {
+                runtime_context.clear();
+                runtime_context.set_in_expression_evaluation(false);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,25 +1,41 @@
+use runtime::RuntimeObject;
 use runtime::choice_point::ChoicePoint;
-use callstack::Thread;
+use callstack::{CallStack, Thread};
+use error::InkError;
+use json_parser::{read_array, read_int, read_str};
 use path::Path;
+use runtime_graph::RuntimeGraph;
 
+use serde_json;
+use serde_json::Map;
+
+/// A choice presented to the player, generated when a choice point is evaluated.
+#[derive(Clone)]
 pub struct Choice {
     text: Option<String>,
+    tags: Vec<String>,
     choice_point: ChoicePoint,
+    /// Path of the choice point in the story.
+    source_path: Option<Path>,
     thread: Thread
 }
 
 impl Choice {
-    pub fn from_choice_point(&self, choice_point: ChoicePoint, thread: Thread) -> Choice {
+    /// Create a choice from the choice point which generated it and the thread active at the time,
+    /// which is where the story resumes when the choice is made.
+    pub fn from_choice_point(choice_point: ChoicePoint, thread: Thread) -> Choice {
         Choice {
             text: None,
+            tags: Vec::new(),
             choice_point: choice_point,
+            source_path: None,
             thread: thread
         }
     }
 
     pub fn text(&self) -> Option<&str> {
         match self.text {
-            Some(ref text) => Some(&text),
+            Some(ref text) => Some(text),
             _ => None
         }
     }
@@ -28,7 +44,99 @@ impl Choice {
         self.text = Some(text)
     }
 
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags
+    }
+
     pub fn path_on_choice(&self) -> Option<&Path> {
         self.choice_point.path_on_choice()
     }
-}
\ No newline at end of file
+
+    /// Returns true for a fallback choice, which is never presented to the player but followed
+    /// automatically when the story runs out of content with no other choice available.
+    pub fn is_invisible_default(&self) -> bool {
+        self.choice_point.is_invisible_default()
+    }
+
+    pub fn choice_point(&self) -> &ChoicePoint {
+        &self.choice_point
+    }
+
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_path.as_ref()
+    }
+
+    pub fn set_source_path(&mut self, source_path: Path) {
+        self.source_path = Some(source_path)
+    }
+
+    pub fn thread(&self) -> &Thread {
+        &self.thread
+    }
+
+    /// Write the choice in the JSON format of the story state, given its index in the visible
+    /// choices. Its thread is written separately when it is no longer active.
+    pub fn to_json(&self, index: usize) -> serde_json::Value {
+        let path_to_string = |path: Option<&Path>| serde_json::Value::String(path.map(|path| path.to_string()).unwrap_or_default());
+
+        let mut json = Map::new();
+        json.insert("text".to_owned(), serde_json::Value::String(self.text().unwrap_or("").to_owned()));
+        json.insert("index".to_owned(), serde_json::Value::Number(index.into()));
+        json.insert("originalChoicePath".to_owned(), path_to_string(self.source_path()));
+        json.insert("originalThreadIndex".to_owned(), serde_json::Value::Number(self.thread.index().into()));
+        json.insert("targetPath".to_owned(), path_to_string(self.path_on_choice()));
+
+        if !self.tags.is_empty() {
+            json.insert("tags".to_owned(), serde_json::Value::Array(self.tags.iter().cloned().map(serde_json::Value::String).collect()));
+        }
+
+        serde_json::Value::Object(json)
+    }
+
+    /// Read a choice written by `to_json`. Its thread is copied from the active threads of the
+    /// callstack, or read from the threads saved along with the choices.
+    pub fn from_json(json: &serde_json::Value, runtime_graph: &RuntimeGraph, callstack: &CallStack, choice_threads: Option<&serde_json::Value>) -> Result<Choice, InkError> {
+        let source_path = read_str(json, "originalChoicePath")?;
+        let (mut choice_point, source_path) = match Path::from_str(source_path).and_then(|path| runtime_graph.resolve_path(&path).map(|runtime_object| (runtime_object, path))) {
+            Some((&RuntimeObject::Choice(ref choice_point), path)) => (choice_point.clone(), path),
+            _ => return Err(InkError::from_message(format!("Failed to find choice point at path {} when loading the state", source_path)))
+        };
+
+        let target_path = read_str(json, "targetPath")?;
+        match Path::from_str(target_path) {
+            Some(path) => choice_point.set_path_on_choice(path),
+            _ => return Err(InkError::from_message(format!("Invalid choice target path {} in the story state", target_path)))
+        }
+
+        let thread_index = read_int(json, "originalThreadIndex")? as usize;
+        let thread = match callstack.thread_with_index(thread_index) {
+            Some(thread) => thread.clone(),
+            _ => match choice_threads.and_then(|choice_threads| choice_threads.get(thread_index.to_string())) {
+                Some(thread) => Thread::from_json(thread, runtime_graph.root_container())?,
+                _ => return Err(InkError::from_message(format!("Missing thread {} of a choice in the story state", thread_index)))
+            }
+        };
+
+        let mut choice = Choice::from_choice_point(choice_point, thread);
+        choice.set_text(read_str(json, "text")?.to_owned());
+        choice.set_source_path(source_path);
+
+        if json.get("tags").is_some() {
+            let mut tags = Vec::new();
+            for tag in read_array(json, "tags")? {
+                match tag.as_str() {
+                    Some(tag) => tags.push(tag.to_owned()),
+                    _ => return Err(InkError::from_message("Expected a string for a choice tag in the story state"))
+                }
+            }
+
+            choice.set_tags(tags);
+        }
+
+        Ok(choice)
+    }
+}
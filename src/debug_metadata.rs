@@ -1,5 +1,7 @@
 use std::fmt;
 
+// Not attached to the runtime objects read from the compiled story yet.
+#[allow(dead_code)]
 pub struct DebugMetadata {
     start_line_number: u32,
     end_line_number: u32,
@@ -7,6 +9,7 @@ pub struct DebugMetadata {
     source_name: Option<String>
 }
 
+#[allow(dead_code)]
 impl DebugMetadata {
     pub fn new() -> DebugMetadata {
         DebugMetadata {
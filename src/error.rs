@@ -1,7 +1,6 @@
 use std::error;
 use std::fmt::{self, Debug, Display};
 use std::io;
-use std::result;
 
 use serde_json;
 
@@ -24,6 +23,14 @@ impl InkError {
             code: code
         }
     }
+
+    pub fn from_message<S: Into<String>>(message: S) -> Self {
+        InkError::new(InkErrorCode::Message(message.into()))
+    }
+
+    pub fn code(&self) -> &InkErrorCode {
+        &self.code
+    }
 }
 
 impl From<serde_json::Error> for InkError {
@@ -51,20 +58,10 @@ impl Display for InkErrorCode {
 }
 
 impl error::Error for InkError {
-    fn description(&self) -> &str {
-        match self.code {
-            InkErrorCode::Io(ref err) => error::Error::description(err),
-            InkErrorCode::Json(ref err) => serde_json::Error::description(err),
-            _ => {
-                // If you want a better message, use Display::fmt or to_string().
-                "Ink error"
-            }
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.code {
             InkErrorCode::Io(ref err) => Some(err),
+            InkErrorCode::Json(ref err) => Some(err),
             _ => None,
         }
     }
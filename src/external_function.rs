@@ -0,0 +1,29 @@
+use runtime::value::Value;
+
+pub type ExternalFunctionCallback = Box<dyn FnMut(&[Value]) -> Option<Value>>;
+
+/// Function of the game bound to an EXTERNAL function declared in ink.
+pub struct ExternalFunction {
+    function: ExternalFunctionCallback,
+    lookahead_safe: bool
+}
+
+impl ExternalFunction {
+    /// Create an external function. A function which is not lookahead safe is never called
+    /// while the story evaluates ahead of a newline to check that the line is over, at the cost
+    /// of ending the line as soon as the function is reached.
+    pub fn new(function: ExternalFunctionCallback, lookahead_safe: bool) -> ExternalFunction {
+        ExternalFunction {
+            function: function,
+            lookahead_safe: lookahead_safe
+        }
+    }
+
+    pub fn lookahead_safe(&self) -> bool {
+        self.lookahead_safe
+    }
+
+    pub fn call(&mut self, arguments: &[Value]) -> Option<Value> {
+        (self.function)(arguments)
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::io::Read;
@@ -11,11 +11,14 @@ use runtime::RuntimeObject;
 use runtime::value::Value;
 use runtime::glue::Glue;
 use runtime::control_command::ControlCommand;
-use runtime::divert::{Divert, PushPopType, TargetType};
+use runtime::divert::{Divert, TargetType};
 use runtime::choice_point::ChoicePoint;
+use runtime::native_function_call::NativeFunctionCall;
 use runtime::variable::{VariableAssignment, VariableReference, ReadCount};
 use runtime::tag::Tag;
 use runtime::container::Container;
+use runtime::ink_list::{InkList, InkListItem};
+use runtime::list_definition::{ListDefinition, ListDefinitions};
 use runtime_graph::RuntimeGraph;
 
 use serde::de::Error as SerdeError;
@@ -61,12 +64,15 @@ impl<'de> Visitor<'de> for RuntimeGraphVisitor
             _ => None
         }.ok_or(SerdeError::custom("Invalid runtime graph format, expected root"))?;
 
-        let list_defs = match map.next_entry()? as Option<(&str, ListDefinitions)> {
+        let list_definitions = match map.next_entry()? as Option<(&str, ListDefinitions)> {
             Some(("listDefs", value)) => Some(value),
             _ => None
         }.ok_or(SerdeError::custom("Invalid runtime graph format, expected listDefs"))?;
 
-        Ok(RuntimeGraph::new(ink_version, container))
+        let mut runtime_graph = RuntimeGraph::new(ink_version, container);
+        runtime_graph.set_list_definitions(list_definitions);
+
+        Ok(runtime_graph)
     }
 }
 
@@ -206,17 +212,28 @@ impl<'de> Visitor<'de> for RuntimeObjectVisitor
             "end" => Ok(RuntimeObject::ControlCommand(ControlCommand::End)),
             "listInt" => Ok(RuntimeObject::ControlCommand(ControlCommand::ListFromInt)),
             "range" => Ok(RuntimeObject::ControlCommand(ControlCommand::ListRange)),
-
-            // Native functions
-            //Some("L^") => {},
+            "lrnd" => Ok(RuntimeObject::ControlCommand(ControlCommand::ListRandom)),
+            "#" => Ok(RuntimeObject::ControlCommand(ControlCommand::BeginTag)),
+            "/#" => Ok(RuntimeObject::ControlCommand(ControlCommand::EndTag)),
 
             // Void
             "void" => Ok(RuntimeObject::Void),
 
-            _ => Err(SerdeError::custom("Invalid String"))
+            // Native functions
+            _ => match NativeFunctionCall::from_name(v) {
+                Some(native_function_call) => Ok(RuntimeObject::NativeFunctionCall(native_function_call)),
+                _ => Err(SerdeError::custom("Invalid String"))
+            }
         }
     }
 
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+        where
+            E: Error,
+    {
+        Ok(RuntimeObject::Value(Value::Bool(v)))
+    }
+
     fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
         where
             A: MapAccess<'de>,
@@ -430,6 +447,10 @@ impl<'de> Visitor<'de> for RuntimeObjectVisitor
                     let value: Option<&str> = map.next_value()?;
                     match value {
                         Some(name) => {
+                            if let Some(("re", re)) = map.next_entry()? as Option<(&str, bool)> {
+                                return Ok(RuntimeObject::VariableAssignment(VariableAssignment::new(name.to_owned(), !re, false)))
+                            }
+
                             return Ok(RuntimeObject::VariableAssignment(VariableAssignment::new(name.to_owned(), true, false)))
                         },
                         _ => return Err(SerdeError::custom("Unexpected temporary variable type"))
@@ -448,7 +469,16 @@ impl<'de> Visitor<'de> for RuntimeObjectVisitor
                 },
 
                 // List
-                "list" => { return Err(SerdeError::custom("TODO")) },
+                "list" => {
+                    let mut ink_list: InkList = map.next_value()?;
+
+                    // Case {"list": {}, "origins": ["listName"]}
+                    if let Some(("origins", origin_names)) = map.next_entry()? as Option<(&str, Vec<String>)> {
+                        ink_list.add_origin_names(origin_names);
+                    }
+
+                    return Ok(RuntimeObject::Value(Value::List(ink_list)))
+                },
 
                 _ => {}
             }
@@ -509,7 +539,7 @@ impl<'de> Visitor<'de> for RuntimeObjectVisitor
                                     }
 
                                     if let Some(ref mut container_ref) = opt_container.as_mut() {
-                                        container_ref.add_child(RuntimeObject::Container(sub_container_rc));
+                                        container_ref.add_named_child(key.to_owned(), sub_container_rc);
                                     }
                                 }
                         },
@@ -570,14 +600,11 @@ impl<'de> Deserialize<'de> for RuntimeObject {
     {
         // Instantiate our Visitor and ask the Deserializer to drive
         // it over the input data, resulting in an instance of RuntimeObject.
-        deserializer.deserialize_map(RuntimeObjectVisitor::new())
+        // A runtime object can be any JSON type (string, number, array, map or null).
+        deserializer.deserialize_any(RuntimeObjectVisitor::new())
     }
 }
 
-// TODO
-struct ListDefinitions {
-}
-
 struct ListDefinitionsVisitor {
 }
 
@@ -589,7 +616,7 @@ impl ListDefinitionsVisitor {
 
 impl<'de> Visitor<'de> for ListDefinitionsVisitor
 {
-    // Our Visitor is going to produce a RuntimeGraph.
+    // Our Visitor is going to produce a ListDefinitions.
     type Value = ListDefinitions;
 
     // Format a message stating what data this Visitor expects to receive.
@@ -597,11 +624,18 @@ impl<'de> Visitor<'de> for ListDefinitionsVisitor
         formatter.write_str("List definitions")
     }
 
+    // {"listName": {"itemName": value, ...}, ...}
     fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
         where
             A: MapAccess<'de>,
     {
-        Ok(ListDefinitions{})
+        let mut list_definitions = ListDefinitions::new();
+
+        while let Some((name, items)) = map.next_entry()? as Option<(String, HashMap<String, i32>)> {
+            list_definitions.add_list_definition(ListDefinition::new(name, items));
+        }
+
+        Ok(list_definitions)
     }
 }
 
@@ -615,30 +649,122 @@ impl<'de> Deserialize<'de> for ListDefinitions {
     }
 }
 
+struct InkListVisitor {
+}
+
+impl InkListVisitor {
+    fn new() -> Self {
+        InkListVisitor {}
+    }
+}
+
+impl<'de> Visitor<'de> for InkListVisitor
+{
+    // Our Visitor is going to produce an InkList.
+    type Value = InkList;
+
+    // Format a message stating what data this Visitor expects to receive.
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("List items")
+    }
+
+    // {"listName.itemName": value, ...}, the items are added in the order of the file
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+    {
+        let mut ink_list = InkList::new();
+
+        while let Some((full_name, value)) = map.next_entry()? as Option<(String, i32)> {
+            ink_list.insert(InkListItem::from_full_name(&full_name), value);
+        }
+
+        Ok(ink_list)
+    }
+}
+
+impl<'de> Deserialize<'de> for InkList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        // Instantiate our Visitor and ask the Deserializer to drive
+        // it over the input data, resulting in an instance of InkList.
+        deserializer.deserialize_map(InkListVisitor::new())
+    }
+}
+
 pub struct RuntimeGraphBuilder {}
 
 impl RuntimeGraphBuilder {
     pub fn from_str(s: &str) -> Result<RuntimeGraph, InkError>
     {
-        serde_json::from_str(s).map_err(|e| InkError::from(e))
+        serde_json::from_str(s).map_err(InkError::from)
     }
 
     pub fn from_slice(v: &[u8]) -> Result<RuntimeGraph, InkError>
     {
-        serde_json::from_slice(v).map_err(|e| InkError::from(e))
+        serde_json::from_slice(v).map_err(InkError::from)
     }
 
     pub fn from_reader<R>(rdr: R) -> Result<RuntimeGraph, InkError>
         where
             R: Read
     {
-        serde_json::from_reader(rdr).map_err(|e| InkError::from(e))
+        serde_json::from_reader(rdr).map_err(InkError::from)
     }
 }
 
+/// Read a runtime object of the story state, written in the JSON format of the compiled story.
+pub fn read_runtime_object(json: &serde_json::Value) -> Result<RuntimeObject, InkError> {
+    RuntimeObject::deserialize(json).map_err(InkError::from)
+}
+
+pub fn read_runtime_objects(json: &serde_json::Value) -> Result<Vec<RuntimeObject>, InkError> {
+    Vec::<RuntimeObject>::deserialize(json).map_err(InkError::from)
+}
+
+/// Read a value of the story state, e.g. the value of a variable.
+pub fn read_value(json: &serde_json::Value) -> Result<Value, InkError> {
+    match read_runtime_object(json)? {
+        RuntimeObject::Value(value) => Ok(value),
+        runtime_object => Err(InkError::from_message(format!("Expected a value in the story state, got {}", runtime_object)))
+    }
+}
+
+/// Get a field of a JSON object of the story state.
+pub fn read_field<'a>(json: &'a serde_json::Value, key: &str) -> Result<&'a serde_json::Value, InkError> {
+    json.get(key).ok_or_else(|| InkError::from_message(format!("Missing '{}' in the story state", key)))
+}
+
+pub fn read_int(json: &serde_json::Value, key: &str) -> Result<i64, InkError> {
+    read_field(json, key)?.as_i64()
+        .ok_or_else(|| InkError::from_message(format!("Expected an integer for '{}' in the story state", key)))
+}
+
+pub fn read_bool(json: &serde_json::Value, key: &str) -> Result<bool, InkError> {
+    read_field(json, key)?.as_bool()
+        .ok_or_else(|| InkError::from_message(format!("Expected a boolean for '{}' in the story state", key)))
+}
+
+pub fn read_str<'a>(json: &'a serde_json::Value, key: &str) -> Result<&'a str, InkError> {
+    read_field(json, key)?.as_str()
+        .ok_or_else(|| InkError::from_message(format!("Expected a string for '{}' in the story state", key)))
+}
+
+pub fn read_array<'a>(json: &'a serde_json::Value, key: &str) -> Result<&'a Vec<serde_json::Value>, InkError> {
+    read_field(json, key)?.as_array()
+        .ok_or_else(|| InkError::from_message(format!("Expected an array for '{}' in the story state", key)))
+}
+
+pub fn read_object<'a>(json: &'a serde_json::Value, key: &str) -> Result<&'a serde_json::Map<String, serde_json::Value>, InkError> {
+    read_field(json, key)?.as_object()
+        .ok_or_else(|| InkError::from_message(format!("Expected an object for '{}' in the story state", key)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use runtime::divert::PushPopType;
 
     #[test]
     fn value_int_test() {
@@ -741,12 +867,13 @@ mod tests {
 
     #[test]
     fn control_command_test() {
-        let json = "[\"ev\", \"out\", \"/ev\", \"du\", \"pop\", \"~ret\", \"->->\", \"str\", \"/str\", \"nop\", \"choiceCnt\", \"turns\", \"readc\", \"rnd\", \"srnd\", \"visit\", \"seq\", \"thread\", \"done\", \"end\", \"listInt\", \"range\"]";
+        let json = "[\"ev\", \"out\", \"/ev\", \"du\", \"pop\", \"~ret\", \"->->\", \"str\", \"/str\", \"nop\", \"choiceCnt\", \"turns\", \"readc\", \"rnd\", \"srnd\", \"visit\", \"seq\", \"thread\", \"done\", \"end\", \"listInt\", \"range\", \"lrnd\", \"#\", \"/#\"]";
         let control_commands: Vec<ControlCommand> = vec![ControlCommand::EvalStart, ControlCommand::EvalOutput, ControlCommand::EvalEnd, ControlCommand::Duplicate,
             ControlCommand::PopEvaluatedValue, ControlCommand::PopFunction, ControlCommand::PopTunnel, ControlCommand::BeginString, ControlCommand::EndString,
             ControlCommand::NoOp, ControlCommand::ChoiceCount, ControlCommand::TurnsSince, ControlCommand::ReadCount, ControlCommand::Random, ControlCommand::SeedRandom,
             ControlCommand::VisitIndex, ControlCommand::SequenceShuffleIndex, ControlCommand::StartThread, ControlCommand::Done, ControlCommand::End,
-            ControlCommand::ListFromInt, ControlCommand::ListRange];
+            ControlCommand::ListFromInt, ControlCommand::ListRange, ControlCommand::ListRandom,
+            ControlCommand::BeginTag, ControlCommand::EndTag];
 
         let runtime_objects: Vec<RuntimeObject> = serde_json::from_str(json).unwrap();
         assert_eq!(control_commands.len(), runtime_objects.len());
@@ -761,10 +888,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn value_bool_test() {
+        let json = "[true, false]";
+        let runtime_objects: Vec<RuntimeObject> = serde_json::from_str(json).unwrap();
+        assert_eq!(runtime_objects.get(0).unwrap().as_value().unwrap(), &Value::Bool(true));
+        assert_eq!(runtime_objects.get(1).unwrap().as_value().unwrap(), &Value::Bool(false));
+    }
+
+    #[test]
+    fn native_function_call_test() {
+        let json = "[\"+\", \"-\", \"/\", \"*\", \"%\", \"_\", \"==\", \">\", \"<\", \">=\", \"<=\", \"!=\", \"!\", \"&&\", \"||\", \"MIN\", \"MAX\", \"POW\", \"FLOOR\", \"CEILING\", \"INT\", \"FLOAT\", \"?\", \"!?\", \"L^\", \"LIST_MIN\", \"LIST_MAX\", \"LIST_ALL\", \"LIST_COUNT\", \"LIST_VALUE\", \"LIST_INVERT\"]";
+        let native_function_calls: Vec<NativeFunctionCall> = vec![NativeFunctionCall::Add, NativeFunctionCall::Subtract, NativeFunctionCall::Divide,
+            NativeFunctionCall::Multiply, NativeFunctionCall::Mod, NativeFunctionCall::Negate, NativeFunctionCall::Equal, NativeFunctionCall::Greater,
+            NativeFunctionCall::Less, NativeFunctionCall::GreaterThanOrEquals, NativeFunctionCall::LessThanOrEquals, NativeFunctionCall::NotEquals,
+            NativeFunctionCall::Not, NativeFunctionCall::And, NativeFunctionCall::Or, NativeFunctionCall::Min, NativeFunctionCall::Max, NativeFunctionCall::Pow,
+            NativeFunctionCall::Floor, NativeFunctionCall::Ceiling, NativeFunctionCall::Int, NativeFunctionCall::Float, NativeFunctionCall::Has,
+            NativeFunctionCall::Hasnt, NativeFunctionCall::Intersect, NativeFunctionCall::ListMin, NativeFunctionCall::ListMax, NativeFunctionCall::ListAll,
+            NativeFunctionCall::ListCount, NativeFunctionCall::ListValue, NativeFunctionCall::ListInvert];
+
+        let runtime_objects: Vec<RuntimeObject> = serde_json::from_str(json).unwrap();
+        assert_eq!(native_function_calls.len(), runtime_objects.len());
+
+        for (i, runtime_object) in runtime_objects.iter().enumerate() {
+            let native_function_call = native_function_calls.get(i).unwrap();
+
+            match runtime_object {
+                &RuntimeObject::NativeFunctionCall(ref value) => assert_eq!(value, native_function_call),
+                _ => assert!(false)
+            }
+        }
+    }
+
+    #[test]
+    fn value_list_test() {
+        let json = "{\"list\": {\"colours.red\": 1, \"colours.blue\": 3}}";
+        let runtime_object: RuntimeObject = serde_json::from_str(json).unwrap();
+        match runtime_object {
+            RuntimeObject::Value(Value::List(ink_list)) => {
+                assert_eq!(ink_list.len(), 2);
+                assert_eq!(ink_list.list().get(&InkListItem::new("colours".to_owned(), "red".to_owned())), Some(&1));
+                assert_eq!(ink_list.list().get(&InkListItem::new("colours".to_owned(), "blue".to_owned())), Some(&3));
+                assert!(ink_list.origin_names().is_none());
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn value_empty_list_with_origins_test() {
+        let json = "{\"list\": {}, \"origins\": [\"colours\"]}";
+        let runtime_object: RuntimeObject = serde_json::from_str(json).unwrap();
+        match runtime_object {
+            RuntimeObject::Value(Value::List(ink_list)) => {
+                assert!(ink_list.is_empty());
+                assert_eq!(ink_list.origin_names().unwrap(), &vec!["colours".to_owned()]);
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn list_definitions_test() {
+        let json = r###"{"inkVersion":17,"root":[["done",null],null],"listDefs":{"colours":{"red":1,"green":2,"blue":3},"moods":{"happy":1}}}"###;
+        let runtime_graph: RuntimeGraph = RuntimeGraphBuilder::from_str(json).unwrap();
+        let list_definitions = runtime_graph.list_definitions();
+
+        assert_eq!(list_definitions.list_definitions().len(), 2);
+        assert_eq!(list_definitions.list_definition("colours").unwrap().items().get("blue"), Some(&3));
+        assert_eq!(list_definitions.list_definition("moods").unwrap().items().get("happy"), Some(&1));
+    }
+
     #[test]
     fn void_test() {
         let json = "[\"void\"]";
-        let runtime_objects: Vec<RuntimeObject> = serde_json::from_str(json).unwrap();
+        let _runtime_objects: Vec<RuntimeObject> = serde_json::from_str(json).unwrap();
         // TODO: impl PartialEq for RuntimeObject
         //assert_eq!(runtime_objects.get(0).unwrap(), RuntimeObject::Void);
     }
@@ -1018,6 +1216,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn temporary_variable_reassignment_test() {
+        let json = "{\"temp=\": \"x\", \"re\": true}";
+        let runtime_object: RuntimeObject = serde_json::from_str(json).unwrap();
+        match runtime_object {
+            RuntimeObject::VariableAssignment(variable) => {
+                assert_eq!(variable.name(), "x");
+                assert_eq!(variable.is_new_declaration(), false);
+                assert_eq!(variable.is_global(), false);
+            },
+            _ => assert!(false)
+        }
+    }
+
     #[test]
     fn tag_test() {
         let json = "{\"#\": \"This is a tag\"}";
@@ -1074,7 +1286,8 @@ mod tests {
         let runtime_object: RuntimeObject = serde_json::from_str(json).unwrap();
         match runtime_object {
             RuntimeObject::Container(container) => {
-                assert_eq!(container.len(), 2);
+                // Named-only sub-containers are not part of the indexed content
+                assert_eq!(container.len(), 1);
                 assert_eq!(container.name().unwrap(), "container");
 
                 match container.get(0).unwrap() {
@@ -1087,7 +1300,7 @@ mod tests {
                     _ => assert!(false)
                 }
 
-                match container.get(1).unwrap() {
+                match container.search_by_name("subContainer").unwrap() {
                     &RuntimeObject::Container(ref sub_container) => {
                         assert_eq!(sub_container.len(), 2);
                         assert_eq!(sub_container.name().unwrap(), "subContainer");
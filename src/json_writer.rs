@@ -0,0 +1,137 @@
+use error::InkError;
+use runtime::RuntimeObject;
+use runtime::value::Value;
+
+use serde_json;
+use serde_json::{Map, Number};
+
+/// Write a runtime object of the story state in the JSON format of the compiled story. Only the
+/// objects which can be found in the output stream or in the evaluation stack can be written.
+pub fn write_runtime_object(runtime_object: &RuntimeObject) -> Result<serde_json::Value, InkError> {
+    match runtime_object {
+        &RuntimeObject::Value(ref value) => Ok(write_value(value)),
+        &RuntimeObject::ControlCommand(ref control_command) => Ok(serde_json::Value::String(control_command.to_string())),
+        &RuntimeObject::Glue(ref glue) => Ok(serde_json::Value::String(glue.to_string())),
+        &RuntimeObject::Tag(ref tag) => {
+            let mut json = Map::new();
+            json.insert("#".to_owned(), serde_json::Value::String(tag.text().clone()));
+            Ok(serde_json::Value::Object(json))
+        },
+        &RuntimeObject::Void => Ok(serde_json::Value::String("void".to_owned())),
+        &RuntimeObject::Null => Ok(serde_json::Value::Null),
+        _ => Err(InkError::from_message(format!("Cannot write {} in the story state", runtime_object)))
+    }
+}
+
+pub fn write_runtime_objects(runtime_objects: &[RuntimeObject]) -> Result<serde_json::Value, InkError> {
+    let mut json = Vec::with_capacity(runtime_objects.len());
+
+    for runtime_object in runtime_objects {
+        json.push(write_runtime_object(runtime_object)?);
+    }
+
+    Ok(serde_json::Value::Array(json))
+}
+
+pub fn write_value(value: &Value) -> serde_json::Value {
+    match value {
+        &Value::Bool(value) => serde_json::Value::Bool(value),
+        &Value::Int(value) => serde_json::Value::Number(value.into()),
+        // Go through the shortest representation of the float, so that 0.1 is not written as
+        // 0.10000000149011612
+        &Value::Float(value) => match value.to_string().parse().ok().and_then(Number::from_f64) {
+            Some(number) => serde_json::Value::Number(number),
+            _ => serde_json::Value::Null
+        },
+        &Value::String(ref text) if text == "\n" => serde_json::Value::String(text.clone()),
+        &Value::String(ref text) => serde_json::Value::String(format!("^{}", text)),
+        &Value::List(ref ink_list) => {
+            let mut items = Map::new();
+            for (item, value) in ink_list.list() {
+                if let Some(full_name) = item.full_name() {
+                    items.insert(full_name, serde_json::Value::Number((*value).into()));
+                }
+            }
+
+            let mut json = Map::new();
+            json.insert("list".to_owned(), serde_json::Value::Object(items));
+
+            // An empty list keeps the lists it comes from, e.g. for LIST_ALL
+            if ink_list.is_empty() {
+                if let Some(origin_names) = ink_list.origin_names().filter(|origin_names| !origin_names.is_empty()) {
+                    json.insert("origins".to_owned(), serde_json::Value::Array(origin_names.iter().cloned().map(serde_json::Value::String).collect()));
+                }
+            }
+
+            serde_json::Value::Object(json)
+        },
+        &Value::DivertTarget(ref path) => {
+            let mut json = Map::new();
+            json.insert("^->".to_owned(), serde_json::Value::String(path.to_string()));
+            serde_json::Value::Object(json)
+        },
+        &Value::VariablePointer(ref name, context_index) => {
+            let mut json = Map::new();
+            json.insert("^var".to_owned(), serde_json::Value::String(name.clone()));
+            json.insert("ci".to_owned(), serde_json::Value::Number(context_index.into()));
+            serde_json::Value::Object(json)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json_parser::read_runtime_object;
+    use path::Path;
+    use runtime::glue::Glue;
+    use runtime::control_command::ControlCommand;
+    use runtime::ink_list::{InkList, InkListItem};
+    use runtime::tag::Tag;
+
+    #[test]
+    fn round_trip_test() {
+        let mut ink_list = InkList::new();
+        ink_list.insert(InkListItem::new("colours".to_owned(), "red".to_owned()), 1);
+
+        let values = vec![
+            Value::Bool(true),
+            Value::Int(42),
+            Value::Float(0.1),
+            Value::Float(2.0),
+            Value::String("Hello".to_owned()),
+            Value::String("\n".to_owned()),
+            Value::List(ink_list),
+            Value::List(InkList::from_origin_names(vec!["colours".to_owned()])),
+            Value::DivertTarget(Path::from_str("knot.stitch").unwrap()),
+            Value::VariablePointer("x".to_owned(), 2)
+        ];
+
+        for value in values {
+            let json = write_value(&value);
+
+            match read_runtime_object(&json).unwrap() {
+                RuntimeObject::Value(read) => {
+                    assert_eq!(read, value);
+                    if let Value::List(ref ink_list) = read {
+                        assert_eq!(ink_list.origin_names(), value.as_list().unwrap().origin_names());
+                    }
+                },
+                runtime_object => panic!("Expected {}, got {}", value, runtime_object)
+            }
+        }
+
+        assert_eq!(write_value(&Value::Float(0.1)).to_string(), "0.1");
+        assert_eq!(write_value(&Value::Float(2.0)).to_string(), "2.0");
+
+        let runtime_objects = vec![
+            RuntimeObject::Glue(Glue::Bidirectional),
+            RuntimeObject::ControlCommand(ControlCommand::BeginString),
+            RuntimeObject::Tag(Tag::new("tag".to_owned())),
+            RuntimeObject::Void
+        ];
+
+        let json = write_runtime_objects(&runtime_objects).unwrap();
+        assert_eq!(json.to_string(), r##"["<>","str",{"#":"tag"},"void"]"##);
+    }
+}
@@ -1,3 +1,11 @@
+// The code base favours explicit field initialisation, reference patterns in matches and
+// explicit returns, keep clippy quiet about those.
+#![allow(clippy::redundant_field_names, clippy::needless_return, clippy::match_ref_pats,
+         clippy::needless_borrowed_reference, clippy::match_like_matches_macro,
+         clippy::collapsible_match)]
+#![cfg_attr(test, allow(clippy::assertions_on_constants, clippy::bool_assert_comparison,
+                        clippy::approx_constant, clippy::excessive_precision, clippy::get_first))]
+
 extern crate serde;
 extern crate serde_json;
 
@@ -8,11 +16,22 @@ mod callstack;
 mod choice;
 mod debug_metadata;
 mod error;
-mod ink_list;
+mod external_function;
 mod json_parser;
+mod json_writer;
 mod path;
+mod prng;
 mod runtime;
 mod runtime_context;
 mod runtime_graph;
 mod story;
 mod story_state;
+mod variable_observer;
+mod variables_state;
+
+pub use choice::Choice;
+pub use error::InkError;
+pub use runtime::ink_list::{InkList, InkListItem};
+pub use runtime::value::{Value, ValueType};
+pub use story::{Story, StoryFlow};
+pub use variable_observer::VariableObserverHandle;
@@ -1,6 +1,6 @@
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::slice::{Iter, IterMut};
+use std::slice::Iter;
 
 #[derive(Clone, PartialEq, Hash, Debug)]
 pub enum Fragment {
@@ -8,6 +8,18 @@ pub enum Fragment {
     Name(String)
 }
 
+/// Name of the fragment leading to the parent container.
+pub const PARENT_FRAGMENT: &str = "^";
+
+impl Fragment {
+    pub fn is_parent(&self) -> bool {
+        match *self {
+            Fragment::Name(ref name) => name == PARENT_FRAGMENT,
+            _ => false
+        }
+    }
+}
+
 impl fmt::Display for Fragment {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -24,7 +36,7 @@ pub struct Path {
 }
 
 impl Path {
-    fn from_fragments(fragments: Vec<Fragment>, is_relative: bool) -> Path {
+    pub fn from_fragments(fragments: Vec<Fragment>, is_relative: bool) -> Path {
         Path {
             fragments: fragments,
             is_relative: is_relative
@@ -43,7 +55,7 @@ impl Path {
         self.fragments.last()
     }
 
-    pub fn iter(&self) -> Iter<Fragment> {
+    pub fn iter(&self) -> Iter<'_, Fragment> {
         self.fragments.iter()
     }
 
@@ -51,6 +63,32 @@ impl Path {
         self.fragments.len()
     }
 
+    /// Get the absolute path of a path relative to some content, given the absolute path of the
+    /// container of that content. The first parent fragment (^) leads to this container, the
+    /// next ones to its parents. Returns None if the path leads above the root container.
+    pub fn to_absolute(&self, container_path: &Path) -> Option<Path> {
+        if !self.is_relative {
+            return Some(self.clone());
+        }
+
+        let mut fragments = container_path.fragments.clone();
+        let mut iter = self.fragments.iter().peekable();
+
+        if iter.peek().is_some_and(|fragment| fragment.is_parent()) {
+            iter.next();
+        }
+
+        for fragment in iter {
+            if fragment.is_parent() {
+                try_opt!(fragments.pop());
+            } else {
+                fragments.push(fragment.clone());
+            }
+        }
+
+        Some(Path::from_fragments(fragments, false))
+    }
+
     pub fn from_str(path: &str) -> Option<Path> {
         if path.is_empty() {
             return None;
@@ -82,7 +120,7 @@ impl Path {
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.is_relative {
-            try!(write!(f, "."));
+            write!(f, ".")?;
         }
 
         write!(f, "{}", self.fragments.iter().map(|ref fragment| fragment.to_string()).collect::<Vec<_>>().join("."))
@@ -107,4 +145,31 @@ impl Hash for Path {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.to_string().hash(state);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_absolute_test() {
+        let container_path = Path::from_str("knot.0.s0").unwrap();
+
+        // The first parent fragment leads to the container itself
+        let path = Path::from_str(".^.^.17").unwrap();
+        assert_eq!(path.to_absolute(&container_path).unwrap().to_string(), "knot.0.17");
+
+        let path = Path::from_str(".^").unwrap();
+        assert_eq!(path.to_absolute(&container_path).unwrap().to_string(), "knot.0.s0");
+
+        let path = Path::from_str(".^.c-0").unwrap();
+        assert_eq!(path.to_absolute(&container_path).unwrap().to_string(), "knot.0.s0.c-0");
+
+        let path = Path::from_str(".^.^.^.^.^").unwrap();
+        assert!(path.to_absolute(&container_path).is_none());
+
+        // Absolute paths are left untouched
+        let path = Path::from_str("other.0").unwrap();
+        assert_eq!(path.to_absolute(&container_path).unwrap(), path);
+    }
+}
@@ -0,0 +1,95 @@
+/// Port of the seeded .NET `System.Random` generator (Knuth's subtractive method), as used by
+/// the C# reference ink runtime, so that the same seed gives the same random numbers and
+/// shuffles as the reference. The arithmetic wraps around like the unchecked C# version.
+///
+/// See https://referencesource.microsoft.com/#mscorlib/system/random.cs
+pub struct Prng {
+    inext: usize,
+    inextp: usize,
+    seed_array: [i32; 56]
+}
+
+const MBIG: i32 = i32::MAX;
+const MSEED: i32 = 161803398;
+
+impl Prng {
+    pub fn new(seed: i32) -> Prng {
+        let subtraction = if seed == i32::MIN { i32::MAX } else { seed.abs() };
+        let mut seed_array = [0; 56];
+
+        let mut mj = MSEED - subtraction;
+        seed_array[55] = mj;
+        let mut mk = 1;
+
+        for i in 1..55 {
+            let ii = (21 * i) % 55;
+            seed_array[ii] = mk;
+            mk = mj.wrapping_sub(mk);
+            if mk < 0 {
+                mk = mk.wrapping_add(MBIG);
+            }
+            mj = seed_array[ii];
+        }
+
+        for _ in 1..5 {
+            for i in 1..56 {
+                seed_array[i] = seed_array[i].wrapping_sub(seed_array[1 + (i + 30) % 55]);
+                if seed_array[i] < 0 {
+                    seed_array[i] = seed_array[i].wrapping_add(MBIG);
+                }
+            }
+        }
+
+        Prng {
+            inext: 0,
+            inextp: 21,
+            seed_array: seed_array
+        }
+    }
+
+    /// Get the next number, always between 0 and 2147483646, like `Random.Next()`.
+    pub fn next(&mut self) -> i32 {
+        self.inext = if self.inext + 1 >= 56 { 1 } else { self.inext + 1 };
+        self.inextp = if self.inextp + 1 >= 56 { 1 } else { self.inextp + 1 };
+
+        let mut value = self.seed_array[self.inext].wrapping_sub(self.seed_array[self.inextp]);
+
+        if value == MBIG {
+            value -= 1;
+        }
+
+        if value < 0 {
+            value = value.wrapping_add(MBIG);
+        }
+
+        self.seed_array[self.inext] = value;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prng_test() {
+        let mut prng = Prng::new(0);
+        assert_eq!(prng.next(), 1559595546);
+        assert_eq!(prng.next(), 1755192844);
+        assert_eq!(prng.next(), 1649316166);
+
+        let mut prng = Prng::new(42);
+        let mut other = Prng::new(42);
+        for _ in 0..100 {
+            assert_eq!(prng.next(), other.next());
+        }
+    }
+
+    #[test]
+    fn prng_seed_test() {
+        // Only the absolute value of the seed matters
+        assert_eq!(Prng::new(42).next(), 1434747710);
+        assert_eq!(Prng::new(-42).next(), 1434747710);
+        assert_eq!(Prng::new(i32::MIN).next(), Prng::new(i32::MAX).next());
+    }
+}
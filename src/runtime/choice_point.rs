@@ -2,6 +2,7 @@ use std::fmt;
 
 use path::Path;
 
+#[derive(Clone)]
 pub struct ChoicePoint {
     has_condition: bool,
     has_start_content: bool,
@@ -94,7 +95,9 @@ impl ChoicePoint {
 
 impl fmt::Display for ChoicePoint {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // TODO
-        write!(f, "")
+        match self.path_on_choice {
+            Some(ref path) => write!(f, "Choice: -> {}", path),
+            _ => write!(f, "Choice")
+        }
     }
 }
\ No newline at end of file
@@ -1,8 +1,17 @@
-use path::{Path, Fragment};
 use runtime::RuntimeObject;
 
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const COUNT_FLAG_VISITS: u8 = 0x1;
+const COUNT_FLAG_TURNS: u8 = 0x2;
+const COUNT_FLAG_START_ONLY: u8 = 0x4;
+
 pub struct Container {
     content: Vec<RuntimeObject>,
+    /// Named sub-containers that are not part of the indexed content. They can only be reached
+    /// by name (e.g. the "s" and "c" containers of a choice), never by stepping through content.
+    named_content: HashMap<String, RuntimeObject>,
     name: Option<String>,
     visits_should_be_counted: bool,
     turn_index_should_be_counted: bool,
@@ -13,6 +22,7 @@ impl Container {
     pub fn new() -> Container {
         Container {
             content: Vec::new(),
+            named_content: HashMap::new(),
             name: None,
             visits_should_be_counted: false,
             turn_index_should_be_counted: false,
@@ -23,6 +33,7 @@ impl Container {
     pub fn from_runtime_object_vec(content: Vec<RuntimeObject>) -> Container {
         Container {
             content: content,
+            named_content: HashMap::new(),
             name: None,
             visits_should_be_counted: false,
             turn_index_should_be_counted: false,
@@ -75,46 +86,49 @@ impl Container {
         self.name = Some(name);
     }
 
+    /// Get the counting flags as written in the "#f" field of the JSON format. Counting at start
+    /// only is meaningless on its own, so no flag is reported in that case.
     pub fn count_flags(&self) -> u8 {
         let mut count_flags: u8 = 0;
 
         if self.visits_should_be_counted {
-            count_flags &= 0x1;
+            count_flags |= COUNT_FLAG_VISITS;
         }
 
         if self.turn_index_should_be_counted {
-            count_flags &= 0x2;
+            count_flags |= COUNT_FLAG_TURNS;
         }
 
         if self.count_at_start_only {
-            count_flags &= 0x4;
+            count_flags |= COUNT_FLAG_START_ONLY;
         }
 
-        if count_flags == 0x4 {
+        if count_flags == COUNT_FLAG_START_ONLY {
             0
         } else {
             count_flags
         }
     }
 
+    /// Set all the counting flags from the "#f" field of the JSON format.
     pub fn set_count_flags(&mut self, count_flags: u8) {
-        if count_flags &  0x1 > 0 {
-            self.visits_should_be_counted = true;
-        }
-
-        if count_flags &  0x2 > 0 {
-            self.turn_index_should_be_counted = true;
-        }
-
-        if count_flags &  0x4 > 0 {
-            self.count_at_start_only = true;
-        }
+        self.visits_should_be_counted = count_flags & COUNT_FLAG_VISITS > 0;
+        self.turn_index_should_be_counted = count_flags & COUNT_FLAG_TURNS > 0;
+        self.count_at_start_only = count_flags & COUNT_FLAG_START_ONLY > 0;
     }
 
     pub fn add_child(&mut self, obj: RuntimeObject) {
         self.content.push(obj);
     }
 
+    pub fn add_named_child(&mut self, name: String, container: Rc<Container>) {
+        self.named_content.insert(name, RuntimeObject::Container(container));
+    }
+
+    pub fn named_content(&self) -> &HashMap<String, RuntimeObject> {
+        &self.named_content
+    }
+
     pub fn append(&mut self, mut objects: Vec<RuntimeObject>) {
         self.content.append(&mut objects);
     }
@@ -148,15 +162,73 @@ impl Container {
         }
     }*/
 
+    /// Search a named child, either in the indexed content or in the named-only content.
     pub fn search_by_name(&self, name: &str) -> Option<&RuntimeObject> {
-        for runtime_object in &self.content {
-            if let Some(other_name) = runtime_object.name() {
-                if name == other_name {
-                    return Some(runtime_object)
-                }
+        self.index_of_name(name)
+            .and_then(|index| self.content.get(index))
+            .or_else(|| self.named_content.get(name))
+    }
+
+    /// Get the index of a named child in the indexed content.
+    pub fn index_of_name(&self, name: &str) -> Option<usize> {
+        self.content.iter().position(|runtime_object| runtime_object.name() == Some(name))
+    }
+
+    /// Get the index of a child container in the indexed content. Returns None if the container
+    /// is only part of the named content.
+    pub fn index_of_container(&self, container: &Rc<Container>) -> Option<usize> {
+        self.content.iter().position(|runtime_object| {
+            match runtime_object {
+                &RuntimeObject::Container(ref child) => Rc::ptr_eq(child, container),
+                _ => false
             }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_flags_test() {
+        for count_flags in 0..8u8 {
+            let mut container = Container::new();
+            container.set_count_flags(count_flags);
+
+            assert_eq!(container.visits_should_be_counted(), count_flags & 0x1 > 0);
+            assert_eq!(container.turn_index_should_be_counted(), count_flags & 0x2 > 0);
+            assert_eq!(container.count_at_start_only(), count_flags & 0x4 > 0);
+
+            // Counting at start only is dropped when nothing is counted
+            let expected = if count_flags == 0x4 { 0 } else { count_flags };
+            assert_eq!(container.count_flags(), expected);
+
+            let mut round_trip = Container::new();
+            round_trip.set_count_flags(container.count_flags());
+            assert_eq!(round_trip.count_flags(), expected);
         }
+    }
+
+    #[test]
+    fn count_flags_from_setters_test() {
+        let mut container = Container::new();
+        assert_eq!(container.count_flags(), 0);
+
+        container.set_visits_should_be_counted(true);
+        container.set_count_at_start_only(true);
+        assert_eq!(container.count_flags(), 0x5);
+
+        container.set_turn_index_should_be_counted(true);
+        assert_eq!(container.count_flags(), 0x7);
+
+        container.set_visits_should_be_counted(false);
+        container.set_turn_index_should_be_counted(false);
+        assert_eq!(container.count_flags(), 0);
 
-        None
+        // Setting the flags clears the flags which are not set
+        container.set_count_flags(0x2);
+        assert!(!container.count_at_start_only());
+        assert_eq!(container.count_flags(), 0x2);
     }
 }
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ControlCommand {
     /// Begin logical evaluation mode. In evaluation mode, objects that are encounted are
     /// added to an evaluation stack, rather than simply echoed into the main text output stream.
@@ -73,7 +73,19 @@ pub enum ControlCommand {
     End,
 
     ListFromInt,
-    ListRange
+    ListRange,
+
+    /// Pops a list and pushes a list containing a single item picked at random in it.
+    ListRandom,
+
+    /// Begin a dynamic tag. The content output until the matching `EndTag` is the text of the
+    /// tag rather than text of the line.
+    BeginTag,
+
+    /// End a dynamic tag. During string evaluation (for choices) the content of the tag is
+    /// collapsed and pushed as a tag to the evaluation stack, otherwise the marker is simply
+    /// added to the output stream.
+    EndTag
 }
 
 impl fmt::Display for ControlCommand {
@@ -101,6 +113,9 @@ impl fmt::Display for ControlCommand {
             ControlCommand::End => write!(f, "end"),
             ControlCommand::ListFromInt => write!(f, "listInt"),
             ControlCommand::ListRange => write!(f, "range"),
+            ControlCommand::ListRandom => write!(f, "lrnd"),
+            ControlCommand::BeginTag => write!(f, "#"),
+            ControlCommand::EndTag => write!(f, "/#"),
         }
     }
 }
\ No newline at end of file
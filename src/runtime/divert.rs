@@ -1,18 +1,23 @@
 use path::Path;
 
+use std::fmt;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum PushPopType {
     Tunnel,
     Function,
+    /// Function called by the game with `Story::evaluate_function`, the flow stops when it returns.
+    FunctionEvaluationFromGame,
     None
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum TargetType {
     Name(String),
     Path(Path)
 }
 
+#[derive(Clone)]
 pub struct Divert {
     target: Option<TargetType>,
     stack_push_type: PushPopType,
@@ -102,4 +107,22 @@ impl Divert {
     pub fn set_external_args(&mut self, external_args: u32) {
         self.external_args = Some(external_args);
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for TargetType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TargetType::Name(ref name) => write!(f, "{}", name),
+            TargetType::Path(ref path) => write!(f, "{}", path)
+        }
+    }
+}
+
+impl fmt::Display for Divert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Some(ref target) => write!(f, "Divert(-> {})", target),
+            _ => write!(f, "Divert(null)")
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use error::InkError;
+use runtime::list_definition::{ListDefinition, ListDefinitions};
+
 use std::fmt;
-use std::fmt::Error;
 use std::hash::{Hash, Hasher};
 use std::collections::HashMap;
 
+#[derive(Clone, Debug)]
 pub struct InkListItem {
     origin_name: Option<String>,
     item_name: Option<String>
@@ -27,8 +30,8 @@ impl InkListItem {
         let parts: Vec<&str> = full_name.split(".").collect();
 
         InkListItem {
-            origin_name: parts.get(0).map(|ref part| part.to_string()),
-            item_name: parts.get(1).map(|ref part| part.to_string())
+            origin_name: parts.first().map(|part| part.to_string()),
+            item_name: parts.get(1).map(|part| part.to_string())
         }
     }
 
@@ -93,8 +96,12 @@ impl Hash for InkListItem {
     }
 }
 
+#[derive(Clone, Debug, Default)]
 pub struct InkList {
     ink_list_items: HashMap<InkListItem, i32>,
+    /// The items in the order they were added, which is the order the reference runtime uses
+    /// to pick an item at random.
+    insertion_order: Vec<InkListItem>,
     origin_names: Option<Vec<String>>
 }
 
@@ -102,17 +109,224 @@ impl InkList {
     pub fn new() -> InkList {
         InkList {
             ink_list_items: HashMap::new(),
+            insertion_order: Vec::new(),
             origin_names: None
         }
     }
 
+    /// Create a list containing a single item.
+    pub fn from_item(item: InkListItem, value: i32) -> InkList {
+        let mut ink_list = InkList::new();
+        ink_list.insert(item, value);
+        ink_list
+    }
+
     pub fn list(&self) -> &HashMap<InkListItem, i32> {
         &self.ink_list_items
     }
 
-    pub fn add_item(&self, item: InkListItem) -> bool {
-        // TODO
-        false
+    pub fn len(&self) -> usize {
+        self.ink_list_items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ink_list_items.is_empty()
+    }
+
+    /// Insert an item with its value, replacing the value if the item is already in the list.
+    pub fn insert(&mut self, item: InkListItem, value: i32) {
+        if self.ink_list_items.insert(item.clone(), value).is_none() {
+            self.insertion_order.push(item);
+        }
+    }
+
+    /// Get the items in the order they were added to the list.
+    pub fn items(&self) -> Vec<(&InkListItem, i32)> {
+        self.insertion_order.iter()
+            .map(|item| (item, self.ink_list_items[item]))
+            .collect()
+    }
+
+    /// Create an empty list which still knows the list definitions it relates to, so that
+    /// items can later be added to it by name, or inverted.
+    pub fn from_origin_names(origin_names: Vec<String>) -> InkList {
+        let mut ink_list = InkList::new();
+        ink_list.add_origin_names(origin_names);
+        ink_list
+    }
+
+    /// Get the origin names the list was initially created with, see `all_origin_names` for
+    /// the names derived from the items too.
+    pub fn origin_names(&self) -> Option<&Vec<String>> {
+        self.origin_names.as_ref()
+    }
+
+    /// Get the sorted names of all the list definitions known to this list: the ones it was
+    /// created with and the ones its items come from.
+    pub fn all_origin_names(&self) -> Vec<String> {
+        let mut origin_names = self.origin_names.clone().unwrap_or_default();
+
+        for item in self.ink_list_items.keys() {
+            if let Some(origin_name) = item.origin_name() {
+                origin_names.push(origin_name.clone());
+            }
+        }
+
+        origin_names.sort();
+        origin_names.dedup();
+        origin_names
+    }
+
+    /// Get the list definitions known to this list.
+    pub fn origins<'a>(&self, list_definitions: &'a ListDefinitions) -> Vec<&'a ListDefinition> {
+        self.all_origin_names().iter()
+            .filter_map(|origin_name| list_definitions.list_definition(origin_name))
+            .collect()
+    }
+
+    /// Add an item to the list, its value is found in the definition of its origin list which
+    /// must be known to this list.
+    pub fn add_item(&mut self, item: InkListItem, list_definitions: &ListDefinitions) -> Result<(), InkError> {
+        let origin_name = match (item.origin_name(), item.item_name()) {
+            (Some(origin_name), Some(_)) => origin_name.clone(),
+            _ => return Err(InkError::from_message(format!("Could not add the item {} to this list because it has no origin list", item)))
+        };
+
+        let value = match self.origins(list_definitions).iter().find(|origin| origin.name() == origin_name) {
+            Some(origin) => match origin.value_for_item(&item) {
+                Some(value) => value,
+                _ => return Err(InkError::from_message(format!("Could not add the item {} to this list because it doesn't exist in the original list definition in ink.", item)))
+            },
+            _ => return Err(InkError::from_message("Failed to add item to list because the item was from a new list definition that wasn't previously known to this list. Only items from previously known lists can be used, so that the int value can be found."))
+        };
+
+        self.insert(item, value);
+        Ok(())
+    }
+
+    /// Add an item to the list from its name alone, the item must be found in exactly one of the
+    /// list definitions known to this list.
+    pub fn add_item_with_name(&mut self, item_name: &str, list_definitions: &ListDefinitions) -> Result<(), InkError> {
+        let mut found_origin: Option<&ListDefinition> = None;
+
+        for origin in self.origins(list_definitions) {
+            if origin.contains_item_with_name(item_name) {
+                if let Some(found_origin) = found_origin {
+                    return Err(InkError::from_message(format!("Could not add the item {} to this list because it could come from either {} or {}",
+                                                              item_name, origin.name(), found_origin.name())));
+                }
+
+                found_origin = Some(origin);
+            }
+        }
+
+        match found_origin {
+            Some(origin) => {
+                let item = InkListItem::new(origin.name().to_owned(), item_name.to_owned());
+                let value = origin.items()[item_name];
+                self.insert(item, value);
+                Ok(())
+            },
+            _ => Err(InkError::from_message(format!("Could not add the item {} to this list because it isn't known to any list definitions previously associated with this list.", item_name)))
+        }
+    }
+
+    /// Get the items sorted by value, items with the same value are sorted by origin name.
+    pub fn ordered_items(&self) -> Vec<(&InkListItem, i32)> {
+        let mut ordered_items: Vec<(&InkListItem, i32)> = self.ink_list_items.iter()
+            .map(|(item, &value)| (item, value))
+            .collect();
+
+        ordered_items.sort_by(|&(item, value), &(other_item, other_value)| {
+            value.cmp(&other_value).then_with(|| item.origin_name().cmp(&other_item.origin_name()))
+        });
+
+        ordered_items
+    }
+
+    /// Returns a new list that is the combination of the current list and the one passed in.
+    /// Equivalent to calling (list1 + list2) in ink.
+    pub fn union(&self, ink_list: &InkList) -> InkList {
+        let mut origin_names = self.all_origin_names();
+        origin_names.append(&mut ink_list.all_origin_names());
+        origin_names.sort();
+        origin_names.dedup();
+
+        let mut union = self.clone();
+        union.origin_names = Some(origin_names);
+
+        for (item, value) in ink_list.items() {
+            union.insert(item.clone(), value);
+        }
+
+        union
+    }
+
+    /// Returns a new list that is the intersection of the current list with the one passed in.
+    /// Equivalent to calling (list1 ^ list2) in ink.
+    pub fn intersection(&self, ink_list: &InkList) -> InkList {
+        let mut intersection = InkList::from_origin_names(self.all_origin_names());
+
+        for (item, value) in self.items() {
+            if ink_list.list().contains_key(item) {
+                intersection.insert(item.clone(), value);
+            }
+        }
+
+        intersection
+    }
+
+    /// Returns a new list that is the current list with the items of the one passed in removed.
+    /// Equivalent to calling (list1 - list2) in ink.
+    pub fn difference(&self, ink_list: &InkList) -> InkList {
+        let mut difference = InkList::from_origin_names(self.all_origin_names());
+
+        for (item, value) in self.items() {
+            if !ink_list.list().contains_key(item) {
+                difference.insert(item.clone(), value);
+            }
+        }
+
+        difference
+    }
+
+    /// Returns a list containing all the items of the origin lists which are not in the current
+    /// list. Equivalent to calling LIST_INVERT(list) in ink.
+    pub fn inverse(&self, list_definitions: &ListDefinitions) -> InkList {
+        let all = self.all(list_definitions);
+        all.difference(self)
+    }
+
+    /// Returns a list containing all the items of the origin lists, whether they are in the
+    /// current list or not. Equivalent to calling LIST_ALL(list) in ink.
+    pub fn all(&self, list_definitions: &ListDefinitions) -> InkList {
+        let mut all = InkList::from_origin_names(self.all_origin_names());
+
+        // The items of a definition are added by value, the order they are usually declared in
+        for origin in self.origins(list_definitions) {
+            let mut items: Vec<(&String, i32)> = origin.items().iter().map(|(item_name, &value)| (item_name, value)).collect();
+            items.sort_by(|&(item_name, value), &(other_item_name, other_value)| value.cmp(&other_value).then_with(|| item_name.cmp(other_item_name)));
+
+            for (item_name, value) in items {
+                all.insert(InkListItem::new(origin.name().to_owned(), item_name.clone()), value);
+            }
+        }
+
+        all
+    }
+
+    /// Returns a list containing the items of the current list whose values are within the given
+    /// inclusive bounds. Equivalent to calling LIST_RANGE(list, min, max) in ink.
+    pub fn range(&self, min_value: i32, max_value: i32) -> InkList {
+        let mut range = InkList::from_origin_names(self.all_origin_names());
+
+        for (item, value) in self.items() {
+            if value >= min_value && value <= max_value {
+                range.insert(item.clone(), value);
+            }
+        }
+
+        range
     }
 
     pub fn add_origin_name(&mut self, origin_name: String) {
@@ -134,49 +348,22 @@ impl InkList {
 
     /// Get the maximum item in the list, equivalent to calling LIST_MAX(list) in ink.
     pub fn max_item(&self) -> Option<(&InkListItem, i32)> {
-        if self.ink_list_items.is_empty() {
-            return None;
-        }
-
-        let mut max = 0;
-        let mut max_item: Option<&InkListItem> = None;
-        for (item, &value) in self.ink_list_items.iter() {
-            if value > max {
-                max = value;
-                max_item = Some(&item);
-            }
-        }
-
-        match max_item {
-            Some(item) => Some((item, max)),
-            _ => None
-        }
+        self.ordered_items().last().cloned()
     }
 
     /// Get the minimum item in the list, equivalent to calling LIST_MIN(list) in ink.
     pub fn min_item(&self) -> Option<(&InkListItem, i32)> {
-        if self.ink_list_items.is_empty() {
-            return None;
-        }
-
-        let mut min = i32::max_value();
-        let mut min_item: Option<&InkListItem> = None;
-        for (item, &value) in self.ink_list_items.iter() {
-            if value < min {
-                min = value;
-                min_item = Some(&item);
-            }
-        }
-
-        match min_item {
-            Some(item) => Some((item, min)),
-            _ => None
-        }
+        self.ordered_items().first().cloned()
     }
 
     /// Returns true if the current list contains all the items that are in the list that
-    /// is passed in. Equivalent to calling (list1 ? list2) in ink.
+    /// is passed in. Equivalent to calling (list1 ? list2) in ink, which is false as soon as
+    /// one of the lists is empty.
     pub fn contains(&self, ink_list: &InkList) -> bool {
+        if ink_list.is_empty() || self.is_empty() {
+            return false;
+        }
+
         for (item, _) in ink_list.list().iter() {
             if !self.ink_list_items.contains_key(item) {
                 return false;
@@ -201,13 +388,45 @@ impl InkList {
         }
     }
 
+    /// Returns true if the item values in the current list overlap or are all greater than the
+    /// item values in the passed in list. Equivalent to calling (list1 >= list2) in ink.
+    pub fn greater_than_or_equals(&self, ink_list: &InkList) -> bool {
+        match (self.min_item(), self.max_item()) {
+            (Some((_, min)), Some((_, max))) => {
+                match (ink_list.min_item(), ink_list.max_item()) {
+                    (Some((_, other_min)), Some((_, other_max))) => {
+                        min >= other_min && max >= other_max
+                    },
+                    _ => true
+                }
+            },
+            _ => false
+        }
+    }
+
+    /// Returns true if the item values in the current list overlap or are all less than the
+    /// item values in the passed in list. Equivalent to calling (list1 <= list2) in ink.
+    pub fn less_than_or_equals(&self, ink_list: &InkList) -> bool {
+        match (ink_list.min_item(), ink_list.max_item()) {
+            (Some((_, other_min)), Some((_, other_max))) => {
+                match (self.min_item(), self.max_item()) {
+                    (Some((_, min)), Some((_, max))) => {
+                        max <= other_max && min <= other_min
+                    },
+                    _ => true
+                }
+            },
+            _ => false
+        }
+    }
+
     /// Returns true if all the item values in the current list are less than all the
     /// item values in the passed in list. Equivalent to calling (list1 < list2) in ink.
     pub fn less_than(&self, ink_list: &InkList) -> bool {
-        match self.max_item() {
-            Some((_, value)) => {
-                match ink_list.min_item() {
-                    Some((_, other_value)) => {
+        match ink_list.min_item() {
+            Some((_, other_value)) => {
+                match self.max_item() {
+                    Some((_, value)) => {
                         value < other_value
                     },
                     _ => true
@@ -225,7 +444,7 @@ impl PartialEq for InkList {
             return false;
         }
 
-        self.contains(other)
+        other.list().keys().all(|item| self.ink_list_items.contains_key(item))
     }
 }
 
@@ -243,22 +462,148 @@ impl fmt::Display for InkList {
             }
         }
 
-        ordered_list.sort_by(|&(ref value, _), &(ref other_value, _)| {
-            value.cmp(other_value)
+        ordered_list.sort_by(|&(value, _), &(other_value, _)| {
+            value.cmp(&other_value)
         });
 
         let mut iter = ordered_list.iter();
-        let mut ink_list_str = String::with_capacity(item_names_len + (ordered_list.len() - 1) * 2);
+        let mut ink_list_str = String::with_capacity(item_names_len + ordered_list.len().saturating_sub(1) * 2);
 
-        if let Some(&(_, ref item_name)) = iter.next() {
+        if let Some(&(_, item_name)) = iter.next() {
             ink_list_str.push_str(item_name)
         }
 
-        for &(_, ref item_name) in iter.next() {
+        for &(_, item_name) in iter {
             ink_list_str.push_str(", ");
             ink_list_str.push_str(item_name);
         }
 
         write!(f, "{}", ink_list_str)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_definitions() -> ListDefinitions {
+        let mut colours = HashMap::new();
+        colours.insert("red".to_owned(), 1);
+        colours.insert("green".to_owned(), 2);
+
+        let mut fruits = HashMap::new();
+        fruits.insert("apple".to_owned(), 1);
+        fruits.insert("green".to_owned(), 2);
+
+        let mut list_definitions = ListDefinitions::new();
+        list_definitions.add_list_definition(ListDefinition::new("colours".to_owned(), colours));
+        list_definitions.add_list_definition(ListDefinition::new("fruits".to_owned(), fruits));
+        list_definitions
+    }
+
+    #[test]
+    fn add_item_test() {
+        let list_definitions = list_definitions();
+        let mut ink_list = InkList::from_origin_names(vec!["colours".to_owned()]);
+
+        assert!(ink_list.add_item(InkListItem::new("colours".to_owned(), "green".to_owned()), &list_definitions).is_ok());
+        assert!(ink_list.add_item_with_name("red", &list_definitions).is_ok());
+        assert_eq!(ink_list.to_string(), "red, green");
+
+        // Unknown item, or item from a list unknown to this list
+        assert!(ink_list.add_item_with_name("blue", &list_definitions).is_err());
+        assert!(ink_list.add_item(InkListItem::new("fruits".to_owned(), "apple".to_owned()), &list_definitions).is_err());
+    }
+
+    #[test]
+    fn add_ambiguous_item_test() {
+        let list_definitions = list_definitions();
+        let mut ink_list = InkList::from_origin_names(vec!["colours".to_owned(), "fruits".to_owned()]);
+
+        assert!(ink_list.add_item_with_name("green", &list_definitions).is_err());
+        assert!(ink_list.add_item_with_name("apple", &list_definitions).is_ok());
+        assert_eq!(ink_list.all_origin_names(), vec!["colours".to_owned(), "fruits".to_owned()]);
+    }
+
+    #[test]
+    fn set_operations_test() {
+        let list_definitions = list_definitions();
+        let mut red_green = InkList::from_origin_names(vec!["colours".to_owned()]);
+        red_green.add_item_with_name("red", &list_definitions).unwrap();
+        red_green.add_item_with_name("green", &list_definitions).unwrap();
+
+        let apple = InkList::from_item(InkListItem::new("fruits".to_owned(), "apple".to_owned()), 1);
+
+        let union = red_green.union(&apple);
+        assert_eq!(union.len(), 3);
+        assert_eq!(union.all_origin_names(), vec!["colours".to_owned(), "fruits".to_owned()]);
+        assert_eq!(union.difference(&red_green), apple);
+        assert_eq!(union.intersection(&apple), apple);
+        assert_eq!(union.range(2, 5).to_string(), "green");
+
+        // The inverse of a list without items still relies on the list's origins
+        let empty = red_green.difference(&red_green);
+        assert!(empty.is_empty());
+        assert_eq!(empty.inverse(&list_definitions), red_green);
+        assert_eq!(apple.all(&list_definitions).len(), 2);
+    }
+    #[test]
+    fn contains_empty_list_test() {
+        let list_definitions = list_definitions();
+        let mut red = InkList::from_origin_names(vec!["colours".to_owned()]);
+        red.add_item_with_name("red", &list_definitions).unwrap();
+
+        let empty = InkList::new();
+
+        assert!(red.contains(&red));
+        assert!(!red.contains(&empty));
+        assert!(!empty.contains(&red));
+        assert!(!empty.contains(&empty));
+
+        // Equality does not rely on containment
+        assert_eq!(empty, InkList::new());
+    }
+
+    #[test]
+    fn compare_empty_lists_test() {
+        let list_definitions = list_definitions();
+        let mut red_green = InkList::from_origin_names(vec!["colours".to_owned()]);
+        red_green.add_item_with_name("red", &list_definitions).unwrap();
+        red_green.add_item_with_name("green", &list_definitions).unwrap();
+
+        let empty = InkList::new();
+
+        assert!(red_green.greater_than_or_equals(&empty));
+        assert!(!empty.greater_than_or_equals(&red_green));
+        assert!(!empty.greater_than_or_equals(&empty));
+
+        assert!(empty.less_than_or_equals(&red_green));
+        assert!(!red_green.less_than_or_equals(&empty));
+        assert!(!empty.less_than_or_equals(&empty));
+
+        assert!(red_green.greater_than(&empty));
+        assert!(!empty.greater_than(&red_green));
+        assert!(empty.less_than(&red_green));
+        assert!(!red_green.less_than(&empty));
+        assert!(!empty.less_than(&empty));
+    }
+    #[test]
+    fn insertion_order_test() {
+        let list_definitions = list_definitions();
+        let mut green_red = InkList::from_origin_names(vec!["colours".to_owned()]);
+        green_red.add_item_with_name("green", &list_definitions).unwrap();
+        green_red.add_item_with_name("red", &list_definitions).unwrap();
+        green_red.add_item_with_name("green", &list_definitions).unwrap();
+
+        let names = |ink_list: &InkList| -> Vec<String> {
+            ink_list.items().iter().map(|&(item, _)| item.item_name().unwrap().clone()).collect()
+        };
+
+        assert_eq!(names(&green_red), vec!["green".to_owned(), "red".to_owned()]);
+        assert_eq!(names(&green_red.range(1, 2)), vec!["green".to_owned(), "red".to_owned()]);
+        assert_eq!(names(&green_red.all(&list_definitions)), vec!["red".to_owned(), "green".to_owned()]);
+
+        // The string of a list is still sorted by value
+        assert_eq!(green_red.to_string(), "red, green");
+    }
+}
@@ -0,0 +1,151 @@
+use runtime::ink_list::{InkList, InkListItem};
+
+use std::collections::HashMap;
+
+/// Definition of a LIST declared in ink, with the values of all its items.
+pub struct ListDefinition {
+    name: String,
+    items: HashMap<String, i32>
+}
+
+impl ListDefinition {
+    pub fn new(name: String, items: HashMap<String, i32>) -> ListDefinition {
+        ListDefinition {
+            name: name,
+            items: items
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the values of the items, keyed by item name.
+    pub fn items(&self) -> &HashMap<String, i32> {
+        &self.items
+    }
+
+    pub fn contains_item_with_name(&self, item_name: &str) -> bool {
+        self.items.contains_key(item_name)
+    }
+
+    /// Get the value of an item, as long as it comes from this list.
+    pub fn value_for_item(&self, item: &InkListItem) -> Option<i32> {
+        if item.origin_name().map(|origin_name| origin_name.as_str()) != Some(self.name.as_str()) {
+            return None;
+        }
+
+        item.item_name().and_then(|item_name| self.items.get(item_name.as_str()).cloned())
+    }
+
+    pub fn item_with_value(&self, value: i32) -> Option<InkListItem> {
+        self.items.iter()
+            .find(|&(_, &item_value)| item_value == value)
+            .map(|(item_name, _)| InkListItem::new(self.name.clone(), item_name.clone()))
+    }
+}
+
+/// Registry of all the LIST definitions of a story.
+pub struct ListDefinitions {
+    lists: HashMap<String, ListDefinition>
+}
+
+impl ListDefinitions {
+    pub fn new() -> ListDefinitions {
+        ListDefinitions {
+            lists: HashMap::new()
+        }
+    }
+
+    pub fn add_list_definition(&mut self, list_definition: ListDefinition) {
+        self.lists.insert(list_definition.name.clone(), list_definition);
+    }
+
+    pub fn list_definition(&self, name: &str) -> Option<&ListDefinition> {
+        self.lists.get(name)
+    }
+
+    pub fn list_definitions(&self) -> &HashMap<String, ListDefinition> {
+        &self.lists
+    }
+
+    /// Find an item from its name, either fully qualified ("list.item") or the item name alone
+    /// ("item"), and return a list containing only this item. An item name alone is only found
+    /// if a single list defines it.
+    pub fn find_single_item_list_with_name(&self, name: &str) -> Option<InkList> {
+        let item = InkListItem::from_full_name(name);
+
+        let mut candidates: Vec<(InkListItem, i32)> = match item.item_name() {
+            Some(_) => {
+                let list_definition = match item.origin_name() {
+                    Some(origin_name) => try_opt!(self.lists.get(origin_name)),
+                    _ => return None
+                };
+
+                vec![(item.clone(), try_opt!(list_definition.value_for_item(&item)))]
+            },
+            _ => self.lists.values()
+                .filter_map(|list_definition| {
+                    list_definition.items.get(name).map(|&value| {
+                        (InkListItem::new(list_definition.name.clone(), name.to_owned()), value)
+                    })
+                })
+                .collect()
+        };
+
+        if candidates.len() != 1 {
+            return None;
+        }
+
+        let (item, value) = candidates.remove(0);
+        Some(InkList::from_item(item, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_definitions() -> ListDefinitions {
+        let mut colours = HashMap::new();
+        colours.insert("red".to_owned(), 1);
+        colours.insert("green".to_owned(), 2);
+
+        let mut fruits = HashMap::new();
+        fruits.insert("apple".to_owned(), 1);
+        fruits.insert("green".to_owned(), 2);
+
+        let mut list_definitions = ListDefinitions::new();
+        list_definitions.add_list_definition(ListDefinition::new("colours".to_owned(), colours));
+        list_definitions.add_list_definition(ListDefinition::new("fruits".to_owned(), fruits));
+        list_definitions
+    }
+
+    #[test]
+    fn list_definition_test() {
+        let list_definitions = list_definitions();
+        let colours = list_definitions.list_definition("colours").unwrap();
+
+        assert_eq!(colours.name(), "colours");
+        assert_eq!(colours.value_for_item(&InkListItem::new("colours".to_owned(), "green".to_owned())), Some(2));
+        assert_eq!(colours.value_for_item(&InkListItem::new("fruits".to_owned(), "green".to_owned())), None);
+        assert_eq!(colours.item_with_value(1), Some(InkListItem::new("colours".to_owned(), "red".to_owned())));
+        assert_eq!(colours.item_with_value(3), None);
+    }
+
+    #[test]
+    fn find_single_item_list_with_name_test() {
+        let list_definitions = list_definitions();
+
+        let red = list_definitions.find_single_item_list_with_name("red").unwrap();
+        assert_eq!(red.list().get(&InkListItem::new("colours".to_owned(), "red".to_owned())), Some(&1));
+
+        let green = list_definitions.find_single_item_list_with_name("fruits.green").unwrap();
+        assert_eq!(green.list().get(&InkListItem::new("fruits".to_owned(), "green".to_owned())), Some(&2));
+
+        // Ambiguous or unknown names
+        assert!(list_definitions.find_single_item_list_with_name("green").is_none());
+        assert!(list_definitions.find_single_item_list_with_name("blue").is_none());
+        assert!(list_definitions.find_single_item_list_with_name("colours.apple").is_none());
+    }
+}
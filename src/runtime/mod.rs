@@ -3,6 +3,8 @@ pub mod container;
 pub mod control_command;
 pub mod divert;
 pub mod glue;
+pub mod ink_list;
+pub mod list_definition;
 pub mod native_function_call;
 pub mod tag;
 pub mod value;
@@ -21,9 +23,7 @@ use runtime::tag::Tag;
 use runtime::value::Value;
 use runtime::variable::{VariableAssignment, VariableReference, ReadCount};
 
-use debug_metadata::DebugMetadata;
-use path::Path;
-
+#[derive(Clone)]
 pub enum RuntimeObject {
     Choice(ChoicePoint),
     Container(Rc<Container>),
@@ -43,7 +43,18 @@ pub enum RuntimeObject {
 impl fmt::Display for RuntimeObject {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &RuntimeObject::ControlCommand(ref control_command) => write!(f, "{}", control_command.to_string()),
+            &RuntimeObject::Choice(ref choice_point) => write!(f, "{}", choice_point),
+            &RuntimeObject::Container(ref container) => write!(f, "Container({})", container.name().unwrap_or("")),
+            &RuntimeObject::ControlCommand(ref control_command) => write!(f, "{}", control_command),
+            &RuntimeObject::Divert(ref divert) => write!(f, "{}", divert),
+            &RuntimeObject::Glue(ref glue) => write!(f, "{}", glue),
+            &RuntimeObject::NativeFunctionCall(ref native_function_call) => write!(f, "{}", native_function_call),
+            &RuntimeObject::Tag(ref tag) => write!(f, "{}", tag),
+            &RuntimeObject::Value(ref value) => write!(f, "{}", value),
+            &RuntimeObject::VariableAssignment(ref variable_assignment) => write!(f, "{}", variable_assignment),
+            &RuntimeObject::VariableReference(ref variable_reference) => write!(f, "{}", variable_reference),
+            &RuntimeObject::ReadCount(ref read_count) => write!(f, "{}", read_count),
+            &RuntimeObject::Void => write!(f, "void"),
             _ => write!(f, "TODO"),
         }
     }
@@ -1,3 +1,490 @@
-pub struct NativeFunctionCall {
+use error::InkError;
+use runtime::ink_list::InkList;
+use runtime::list_definition::ListDefinitions;
+use runtime::value::{Value, ValueType};
 
-}
\ No newline at end of file
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NativeFunctionCall {
+    Add,
+    Subtract,
+    Divide,
+    Multiply,
+    Mod,
+    Negate,
+
+    Equal,
+    Greater,
+    Less,
+    GreaterThanOrEquals,
+    LessThanOrEquals,
+    NotEquals,
+    Not,
+
+    And,
+    Or,
+
+    Min,
+    Max,
+
+    Pow,
+    Floor,
+    Ceiling,
+    Int,
+    Float,
+
+    Has,
+    Hasnt,
+    Intersect,
+
+    ListMin,
+    ListMax,
+    ListAll,
+    ListCount,
+    ListValue,
+    ListInvert
+}
+
+impl NativeFunctionCall {
+    /// Get the native function from its name in the compiled story, e.g. "+" or "MIN".
+    pub fn from_name(name: &str) -> Option<NativeFunctionCall> {
+        match name {
+            "+" => Some(NativeFunctionCall::Add),
+            "-" => Some(NativeFunctionCall::Subtract),
+            "/" => Some(NativeFunctionCall::Divide),
+            "*" => Some(NativeFunctionCall::Multiply),
+            "%" => Some(NativeFunctionCall::Mod),
+            "_" => Some(NativeFunctionCall::Negate),
+            "==" => Some(NativeFunctionCall::Equal),
+            ">" => Some(NativeFunctionCall::Greater),
+            "<" => Some(NativeFunctionCall::Less),
+            ">=" => Some(NativeFunctionCall::GreaterThanOrEquals),
+            "<=" => Some(NativeFunctionCall::LessThanOrEquals),
+            "!=" => Some(NativeFunctionCall::NotEquals),
+            "!" => Some(NativeFunctionCall::Not),
+            "&&" => Some(NativeFunctionCall::And),
+            "||" => Some(NativeFunctionCall::Or),
+            "MIN" => Some(NativeFunctionCall::Min),
+            "MAX" => Some(NativeFunctionCall::Max),
+            "POW" => Some(NativeFunctionCall::Pow),
+            "FLOOR" => Some(NativeFunctionCall::Floor),
+            "CEILING" => Some(NativeFunctionCall::Ceiling),
+            "INT" => Some(NativeFunctionCall::Int),
+            "FLOAT" => Some(NativeFunctionCall::Float),
+            "?" => Some(NativeFunctionCall::Has),
+            "!?" => Some(NativeFunctionCall::Hasnt),
+            "L^" => Some(NativeFunctionCall::Intersect),
+            "LIST_MIN" => Some(NativeFunctionCall::ListMin),
+            "LIST_MAX" => Some(NativeFunctionCall::ListMax),
+            "LIST_ALL" => Some(NativeFunctionCall::ListAll),
+            "LIST_COUNT" => Some(NativeFunctionCall::ListCount),
+            "LIST_VALUE" => Some(NativeFunctionCall::ListValue),
+            "LIST_INVERT" => Some(NativeFunctionCall::ListInvert),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            NativeFunctionCall::Add => "+",
+            NativeFunctionCall::Subtract => "-",
+            NativeFunctionCall::Divide => "/",
+            NativeFunctionCall::Multiply => "*",
+            NativeFunctionCall::Mod => "%",
+            NativeFunctionCall::Negate => "_",
+            NativeFunctionCall::Equal => "==",
+            NativeFunctionCall::Greater => ">",
+            NativeFunctionCall::Less => "<",
+            NativeFunctionCall::GreaterThanOrEquals => ">=",
+            NativeFunctionCall::LessThanOrEquals => "<=",
+            NativeFunctionCall::NotEquals => "!=",
+            NativeFunctionCall::Not => "!",
+            NativeFunctionCall::And => "&&",
+            NativeFunctionCall::Or => "||",
+            NativeFunctionCall::Min => "MIN",
+            NativeFunctionCall::Max => "MAX",
+            NativeFunctionCall::Pow => "POW",
+            NativeFunctionCall::Floor => "FLOOR",
+            NativeFunctionCall::Ceiling => "CEILING",
+            NativeFunctionCall::Int => "INT",
+            NativeFunctionCall::Float => "FLOAT",
+            NativeFunctionCall::Has => "?",
+            NativeFunctionCall::Hasnt => "!?",
+            NativeFunctionCall::Intersect => "L^",
+            NativeFunctionCall::ListMin => "LIST_MIN",
+            NativeFunctionCall::ListMax => "LIST_MAX",
+            NativeFunctionCall::ListAll => "LIST_ALL",
+            NativeFunctionCall::ListCount => "LIST_COUNT",
+            NativeFunctionCall::ListValue => "LIST_VALUE",
+            NativeFunctionCall::ListInvert => "LIST_INVERT"
+        }
+    }
+
+    pub fn number_of_parameters(&self) -> usize {
+        match *self {
+            NativeFunctionCall::Negate |
+            NativeFunctionCall::Not |
+            NativeFunctionCall::Floor |
+            NativeFunctionCall::Ceiling |
+            NativeFunctionCall::Int |
+            NativeFunctionCall::Float |
+            NativeFunctionCall::ListMin |
+            NativeFunctionCall::ListMax |
+            NativeFunctionCall::ListAll |
+            NativeFunctionCall::ListCount |
+            NativeFunctionCall::ListValue |
+            NativeFunctionCall::ListInvert => 1,
+            _ => 2
+        }
+    }
+
+    /// Call the function with the given parameters.
+    ///
+    /// The parameters are first coerced to a single type, the highest of the parameter types (and
+    /// at least an integer), following ink's rules: booleans are promoted to integers, integers
+    /// to floats and numbers to strings. Binary operations involving a list are handled apart,
+    /// the list definitions are needed to find the items of the lists' origins.
+    pub fn call(&self, parameters: Vec<Value>, list_definitions: &ListDefinitions) -> Result<Value, InkError> {
+        if parameters.len() != self.number_of_parameters() {
+            return Err(InkError::from_message(format!("Unexpected number of parameters for {}: expected {}, got {}",
+                                                      self, self.number_of_parameters(), parameters.len())));
+        }
+
+        if parameters.len() == 2 && parameters.iter().any(|value| value.value_type() == ValueType::List) {
+            return self.call_binary_list_operation(&parameters[0], &parameters[1], list_definitions);
+        }
+
+        let value_type = parameters.iter()
+            .map(|value| value.value_type())
+            .fold(ValueType::Int, |value_type, other| value_type.max(other));
+
+        let mut coerced_parameters = Vec::with_capacity(parameters.len());
+        for value in &parameters {
+            match value.cast(value_type) {
+                Some(value) => coerced_parameters.push(value),
+                _ => return Err(InkError::from_message(format!("Cannot cast {} to {:?} to perform {}", value, value_type, self)))
+            }
+        }
+
+        let result = match (coerced_parameters.first(), coerced_parameters.get(1)) {
+            (Some(&Value::Int(x)), None) => self.call_int_unary(x),
+            (Some(&Value::Float(x)), None) => self.call_float_unary(x),
+            (Some(&Value::List(ref x)), None) => self.call_list_unary(x, list_definitions),
+            (Some(&Value::Int(x)), Some(&Value::Int(y))) => self.call_int_binary(x, y)?,
+            (Some(&Value::Float(x)), Some(&Value::Float(y))) => self.call_float_binary(x, y),
+            (Some(&Value::String(ref x)), Some(&Value::String(ref y))) => self.call_string_binary(x, y),
+            (Some(&Value::DivertTarget(ref x)), Some(&Value::DivertTarget(ref y))) => {
+                match *self {
+                    NativeFunctionCall::Equal => Some(Value::Bool(x == y)),
+                    NativeFunctionCall::NotEquals => Some(Value::Bool(x != y)),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+
+        match result {
+            Some(value) => Ok(value),
+            _ => Err(InkError::from_message(format!("Cannot perform operation {} on {:?}", self, value_type)))
+        }
+    }
+
+    fn call_int_unary(&self, x: i32) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Negate => Some(Value::Int(x.wrapping_neg())),
+            NativeFunctionCall::Not => Some(Value::Bool(x == 0)),
+            NativeFunctionCall::Floor |
+            NativeFunctionCall::Ceiling |
+            NativeFunctionCall::Int => Some(Value::Int(x)),
+            NativeFunctionCall::Float => Some(Value::Float(x as f32)),
+            _ => None
+        }
+    }
+
+    fn call_float_unary(&self, x: f32) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Negate => Some(Value::Float(-x)),
+            NativeFunctionCall::Not => Some(Value::Bool(x == 0.0)),
+            NativeFunctionCall::Floor => Some(Value::Float(x.floor())),
+            NativeFunctionCall::Ceiling => Some(Value::Float(x.ceil())),
+            NativeFunctionCall::Int => Some(Value::Int(x as i32)),
+            NativeFunctionCall::Float => Some(Value::Float(x)),
+            _ => None
+        }
+    }
+
+    fn call_list_unary(&self, x: &InkList, list_definitions: &ListDefinitions) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Not => Some(Value::Bool(x.is_empty())),
+            NativeFunctionCall::ListMin => Some(Value::List(x.min_item().map_or_else(InkList::new, |(item, value)| InkList::from_item(item.clone(), value)))),
+            NativeFunctionCall::ListMax => Some(Value::List(x.max_item().map_or_else(InkList::new, |(item, value)| InkList::from_item(item.clone(), value)))),
+            NativeFunctionCall::ListAll => Some(Value::List(x.all(list_definitions))),
+            NativeFunctionCall::ListCount => Some(Value::Int(x.len() as i32)),
+            NativeFunctionCall::ListValue => Some(Value::Int(x.max_item().map_or(0, |(_, value)| value))),
+            NativeFunctionCall::ListInvert => Some(Value::List(x.inverse(list_definitions))),
+            _ => None
+        }
+    }
+
+    fn call_binary_list_operation(&self, x: &Value, y: &Value, list_definitions: &ListDefinitions) -> Result<Value, InkError> {
+        let result = match (x, y) {
+            // list + int and list - int move each item to the item with the resulting value
+            (&Value::List(ref list), &Value::Int(value)) if *self == NativeFunctionCall::Add || *self == NativeFunctionCall::Subtract => {
+                Some(Value::List(self.call_list_increment(list, value, list_definitions)))
+            },
+            (&Value::List(ref x), &Value::List(ref y)) => self.call_list_binary(x, y),
+            // Logical operations with any other type are done on the truthiness of the values
+            _ if *self == NativeFunctionCall::And || *self == NativeFunctionCall::Or => {
+                match (x.is_truthy(), y.is_truthy()) {
+                    (Some(x), Some(y)) => Some(Value::Bool(if *self == NativeFunctionCall::And { x && y } else { x || y })),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+
+        match result {
+            Some(value) => Ok(value),
+            _ => Err(InkError::from_message(format!("Can not call use '{}' operation on {:?} and {:?}", self, x.value_type(), y.value_type())))
+        }
+    }
+
+    fn call_list_increment(&self, list: &InkList, increment: i32, list_definitions: &ListDefinitions) -> InkList {
+        let mut result = InkList::new();
+
+        for (item, value) in list.items() {
+            let target_value = match *self {
+                NativeFunctionCall::Subtract => value.wrapping_sub(increment),
+                _ => value.wrapping_add(increment)
+            };
+
+            let origin = item.origin_name().and_then(|origin_name| list_definitions.list_definition(origin_name));
+            if let Some(incremented_item) = origin.and_then(|origin| origin.item_with_value(target_value)) {
+                result.insert(incremented_item, target_value);
+            }
+        }
+
+        result
+    }
+
+    fn call_list_binary(&self, x: &InkList, y: &InkList) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Add => Some(Value::List(x.union(y))),
+            NativeFunctionCall::Subtract => Some(Value::List(x.difference(y))),
+            NativeFunctionCall::Intersect => Some(Value::List(x.intersection(y))),
+            NativeFunctionCall::Has => Some(Value::Bool(x.contains(y))),
+            NativeFunctionCall::Hasnt => Some(Value::Bool(!x.contains(y))),
+            NativeFunctionCall::Equal => Some(Value::Bool(x == y)),
+            NativeFunctionCall::NotEquals => Some(Value::Bool(x != y)),
+            NativeFunctionCall::Greater => Some(Value::Bool(x.greater_than(y))),
+            NativeFunctionCall::Less => Some(Value::Bool(x.less_than(y))),
+            NativeFunctionCall::GreaterThanOrEquals => Some(Value::Bool(x.greater_than_or_equals(y))),
+            NativeFunctionCall::LessThanOrEquals => Some(Value::Bool(x.less_than_or_equals(y))),
+            NativeFunctionCall::And => Some(Value::Bool(!x.is_empty() && !y.is_empty())),
+            NativeFunctionCall::Or => Some(Value::Bool(!x.is_empty() || !y.is_empty())),
+            _ => None
+        }
+    }
+
+    fn call_int_binary(&self, x: i32, y: i32) -> Result<Option<Value>, InkError> {
+        if y == 0 && (*self == NativeFunctionCall::Divide || *self == NativeFunctionCall::Mod) {
+            return Err(InkError::from_message(format!("Division by zero when performing {}", self)));
+        }
+
+        Ok(match *self {
+            NativeFunctionCall::Add => Some(Value::Int(x.wrapping_add(y))),
+            NativeFunctionCall::Subtract => Some(Value::Int(x.wrapping_sub(y))),
+            NativeFunctionCall::Multiply => Some(Value::Int(x.wrapping_mul(y))),
+            NativeFunctionCall::Divide => Some(Value::Int(x.wrapping_div(y))),
+            NativeFunctionCall::Mod => Some(Value::Int(x.wrapping_rem(y))),
+            NativeFunctionCall::Equal => Some(Value::Bool(x == y)),
+            NativeFunctionCall::Greater => Some(Value::Bool(x > y)),
+            NativeFunctionCall::Less => Some(Value::Bool(x < y)),
+            NativeFunctionCall::GreaterThanOrEquals => Some(Value::Bool(x >= y)),
+            NativeFunctionCall::LessThanOrEquals => Some(Value::Bool(x <= y)),
+            NativeFunctionCall::NotEquals => Some(Value::Bool(x != y)),
+            NativeFunctionCall::And => Some(Value::Bool(x != 0 && y != 0)),
+            NativeFunctionCall::Or => Some(Value::Bool(x != 0 || y != 0)),
+            NativeFunctionCall::Min => Some(Value::Int(x.min(y))),
+            NativeFunctionCall::Max => Some(Value::Int(x.max(y))),
+            NativeFunctionCall::Pow => Some(Value::Float((x as f64).powf(y as f64) as f32)),
+            _ => None
+        })
+    }
+
+    fn call_float_binary(&self, x: f32, y: f32) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Add => Some(Value::Float(x + y)),
+            NativeFunctionCall::Subtract => Some(Value::Float(x - y)),
+            NativeFunctionCall::Multiply => Some(Value::Float(x * y)),
+            NativeFunctionCall::Divide => Some(Value::Float(x / y)),
+            NativeFunctionCall::Mod => Some(Value::Float(x % y)),
+            NativeFunctionCall::Equal => Some(Value::Bool(x == y)),
+            NativeFunctionCall::Greater => Some(Value::Bool(x > y)),
+            NativeFunctionCall::Less => Some(Value::Bool(x < y)),
+            NativeFunctionCall::GreaterThanOrEquals => Some(Value::Bool(x >= y)),
+            NativeFunctionCall::LessThanOrEquals => Some(Value::Bool(x <= y)),
+            NativeFunctionCall::NotEquals => Some(Value::Bool(x != y)),
+            NativeFunctionCall::And => Some(Value::Bool(x != 0.0 && y != 0.0)),
+            NativeFunctionCall::Or => Some(Value::Bool(x != 0.0 || y != 0.0)),
+            NativeFunctionCall::Min => Some(Value::Float(x.min(y))),
+            NativeFunctionCall::Max => Some(Value::Float(x.max(y))),
+            NativeFunctionCall::Pow => Some(Value::Float((x as f64).powf(y as f64) as f32)),
+            _ => None
+        }
+    }
+
+    fn call_string_binary(&self, x: &str, y: &str) -> Option<Value> {
+        match *self {
+            NativeFunctionCall::Add => Some(Value::String(format!("{}{}", x, y))),
+            NativeFunctionCall::Equal => Some(Value::Bool(x == y)),
+            NativeFunctionCall::NotEquals => Some(Value::Bool(x != y)),
+            NativeFunctionCall::Has => Some(Value::Bool(x.contains(y))),
+            NativeFunctionCall::Hasnt => Some(Value::Bool(!x.contains(y))),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for NativeFunctionCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::list_definition::ListDefinition;
+
+    use std::collections::HashMap;
+
+    fn call(name: &str, parameters: Vec<Value>) -> Value {
+        NativeFunctionCall::from_name(name).unwrap().call(parameters, &ListDefinitions::new()).unwrap()
+    }
+
+    fn list_definitions() -> ListDefinitions {
+        let mut colours = HashMap::new();
+        colours.insert("red".to_owned(), 1);
+        colours.insert("green".to_owned(), 2);
+        colours.insert("blue".to_owned(), 3);
+
+        let mut list_definitions = ListDefinitions::new();
+        list_definitions.add_list_definition(ListDefinition::new("colours".to_owned(), colours));
+        list_definitions
+    }
+
+    fn colours(names: &[&str]) -> Value {
+        let list_definitions = list_definitions();
+        let mut list = InkList::from_origin_names(vec!["colours".to_owned()]);
+        for name in names {
+            list.add_item_with_name(name, &list_definitions).unwrap();
+        }
+        Value::List(list)
+    }
+
+    fn call_list(name: &str, parameters: Vec<Value>) -> Value {
+        NativeFunctionCall::from_name(name).unwrap().call(parameters, &list_definitions()).unwrap()
+    }
+
+    #[test]
+    fn int_operations_test() {
+        assert_eq!(call("+", vec![Value::Int(2), Value::Int(3)]), Value::Int(5));
+        assert_eq!(call("-", vec![Value::Int(2), Value::Int(3)]), Value::Int(-1));
+        assert_eq!(call("/", vec![Value::Int(7), Value::Int(2)]), Value::Int(3));
+        assert_eq!(call("%", vec![Value::Int(7), Value::Int(2)]), Value::Int(1));
+        assert_eq!(call("_", vec![Value::Int(7)]), Value::Int(-7));
+        assert_eq!(call("==", vec![Value::Int(7), Value::Int(7)]), Value::Bool(true));
+        assert_eq!(call("!", vec![Value::Int(0)]), Value::Bool(true));
+        assert_eq!(call("MIN", vec![Value::Int(7), Value::Int(2)]), Value::Int(2));
+        assert_eq!(call("POW", vec![Value::Int(2), Value::Int(3)]), Value::Float(8.0));
+        assert_eq!(call("FLOAT", vec![Value::Int(2)]), Value::Float(2.0));
+    }
+
+    #[test]
+    fn float_operations_test() {
+        assert_eq!(call("*", vec![Value::Float(1.5), Value::Float(2.0)]), Value::Float(3.0));
+        assert_eq!(call("FLOOR", vec![Value::Float(1.5)]), Value::Float(1.0));
+        assert_eq!(call("CEILING", vec![Value::Float(1.5)]), Value::Float(2.0));
+        assert_eq!(call("INT", vec![Value::Float(-1.5)]), Value::Int(-1));
+        assert_eq!(call(">=", vec![Value::Float(1.5), Value::Float(1.5)]), Value::Bool(true));
+    }
+
+    #[test]
+    fn coercion_test() {
+        // Integers are promoted to floats
+        assert_eq!(call("+", vec![Value::Int(1), Value::Float(0.5)]), Value::Float(1.5));
+        // Booleans are promoted to integers
+        assert_eq!(call("+", vec![Value::Bool(true), Value::Bool(true)]), Value::Int(2));
+        assert_eq!(call("!", vec![Value::Bool(false)]), Value::Bool(true));
+        // Numbers are converted to strings when used with a string
+        assert_eq!(call("+", vec![Value::String("a".to_owned()), Value::Int(1)]), Value::String("a1".to_owned()));
+        assert_eq!(call("==", vec![Value::Float(1.5), Value::String("1.5".to_owned())]), Value::Bool(true));
+    }
+
+    #[test]
+    fn string_operations_test() {
+        let hello = Value::String("hello".to_owned());
+        let ell = Value::String("ell".to_owned());
+
+        assert_eq!(call("?", vec![hello.clone(), ell.clone()]), Value::Bool(true));
+        assert_eq!(call("!?", vec![hello.clone(), ell.clone()]), Value::Bool(false));
+        assert_eq!(call("!=", vec![hello, ell]), Value::Bool(true));
+    }
+
+    #[test]
+    fn divert_target_operations_test() {
+        use path::Path;
+
+        let knot = Value::DivertTarget(Path::from_str("knot").unwrap());
+        let other = Value::DivertTarget(Path::from_str("other").unwrap());
+
+        assert_eq!(call("==", vec![knot.clone(), knot.clone()]), Value::Bool(true));
+        assert_eq!(call("!=", vec![knot.clone(), other]), Value::Bool(true));
+        assert!(NativeFunctionCall::Add.call(vec![knot.clone(), knot.clone()], &ListDefinitions::new()).is_err());
+        assert!(NativeFunctionCall::Equal.call(vec![knot, Value::Int(1)], &ListDefinitions::new()).is_err());
+    }
+
+    #[test]
+    fn invalid_call_test() {
+        let list_definitions = ListDefinitions::new();
+        assert!(NativeFunctionCall::Divide.call(vec![Value::Int(1), Value::Int(0)], &list_definitions).is_err());
+        assert!(NativeFunctionCall::Add.call(vec![Value::Int(1)], &list_definitions).is_err());
+        assert!(NativeFunctionCall::Has.call(vec![Value::Int(1), Value::Int(1)], &list_definitions).is_err());
+        assert!(NativeFunctionCall::Multiply.call(vec![colours(&["red"]), Value::Int(2)], &list_definitions).is_err());
+    }
+
+    #[test]
+    fn list_operations_test() {
+        assert_eq!(call_list("+", vec![colours(&["red"]), colours(&["blue"])]), colours(&["red", "blue"]));
+        assert_eq!(call_list("-", vec![colours(&["red", "blue"]), colours(&["blue"])]), colours(&["red"]));
+        assert_eq!(call_list("L^", vec![colours(&["red", "blue"]), colours(&["blue", "green"])]), colours(&["blue"]));
+        assert_eq!(call_list("?", vec![colours(&["red", "blue"]), colours(&["blue"])]), Value::Bool(true));
+        assert_eq!(call_list("!?", vec![colours(&["red", "blue"]), colours(&["green"])]), Value::Bool(true));
+        assert_eq!(call_list("==", vec![colours(&["red"]), colours(&["red"])]), Value::Bool(true));
+        assert_eq!(call_list(">", vec![colours(&["blue"]), colours(&["red", "green"])]), Value::Bool(true));
+        assert_eq!(call_list(">=", vec![colours(&["green", "blue"]), colours(&["red", "blue"])]), Value::Bool(true));
+        assert_eq!(call_list("<", vec![colours(&["red", "blue"]), colours(&["blue"])]), Value::Bool(false));
+        assert_eq!(call_list("&&", vec![colours(&["red"]), colours(&[])]), Value::Bool(false));
+        assert_eq!(call_list("||", vec![colours(&["red"]), Value::Int(0)]), Value::Bool(true));
+    }
+
+    #[test]
+    fn list_increment_test() {
+        assert_eq!(call_list("+", vec![colours(&["red", "green"]), Value::Int(1)]), colours(&["green", "blue"]));
+        assert_eq!(call_list("-", vec![colours(&["red", "green"]), Value::Int(1)]), colours(&["red"]));
+    }
+
+    #[test]
+    fn list_functions_test() {
+        assert_eq!(call_list("LIST_ALL", vec![colours(&["red"])]), colours(&["red", "green", "blue"]));
+        assert_eq!(call_list("LIST_INVERT", vec![colours(&["red"])]), colours(&["green", "blue"]));
+        assert_eq!(call_list("LIST_INVERT", vec![colours(&[])]), colours(&["red", "green", "blue"]));
+        assert_eq!(call_list("LIST_COUNT", vec![colours(&["red", "blue"])]), Value::Int(2));
+        assert_eq!(call_list("LIST_MIN", vec![colours(&["green", "blue"])]), colours(&["green"]));
+        assert_eq!(call_list("LIST_MAX", vec![colours(&["red", "green"])]), colours(&["green"]));
+        assert_eq!(call_list("LIST_VALUE", vec![colours(&["red", "blue"])]), Value::Int(3));
+        assert_eq!(call_list("!", vec![colours(&[])]), Value::Bool(true));
+    }
+}
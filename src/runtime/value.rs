@@ -1,18 +1,100 @@
 use path::Path;
+use runtime::ink_list::InkList;
 
 use std::fmt;
 
+/// Type of a value. Types are ordered so that values of different types can be coerced to the
+/// highest one when used together in an operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    List,
+    String,
+    DivertTarget,
+    VariablePointer
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
+    Bool(bool),
     Int(i32),
     Float(f32),
-    //List,
+    List(InkList),
     String(String),
     DivertTarget(Path),
     VariablePointer(String, i32)
 }
 
 impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            &Value::Bool(_) => ValueType::Bool,
+            &Value::Int(_) => ValueType::Int,
+            &Value::Float(_) => ValueType::Float,
+            &Value::List(_) => ValueType::List,
+            &Value::String(_) => ValueType::String,
+            &Value::DivertTarget(_) => ValueType::DivertTarget,
+            &Value::VariablePointer(_, _) => ValueType::VariablePointer
+        }
+    }
+
+    /// Convert the value to the given type, returns None if the conversion is not possible.
+    pub fn cast(&self, value_type: ValueType) -> Option<Value> {
+        if self.value_type() == value_type {
+            return Some(self.clone());
+        }
+
+        match (self, value_type) {
+            (&Value::Bool(value), ValueType::Int) => Some(Value::Int(if value { 1 } else { 0 })),
+            (&Value::Bool(value), ValueType::Float) => Some(Value::Float(if value { 1.0 } else { 0.0 })),
+            (&Value::Int(value), ValueType::Bool) => Some(Value::Bool(value != 0)),
+            (&Value::Int(value), ValueType::Float) => Some(Value::Float(value as f32)),
+            (&Value::Float(value), ValueType::Bool) => Some(Value::Bool(value != 0.0)),
+            (&Value::Float(value), ValueType::Int) => Some(Value::Int(value as i32)),
+            // A list is converted to its highest item
+            (&Value::List(ref value), ValueType::Int) => Some(Value::Int(value.max_item().map_or(0, |(_, value)| value))),
+            (&Value::List(ref value), ValueType::Float) => Some(Value::Float(value.max_item().map_or(0.0, |(_, value)| value as f32))),
+            (&Value::List(ref value), ValueType::String) => Some(Value::String(value.max_item().and_then(|(item, _)| item.full_name()).unwrap_or_default())),
+            (&Value::String(ref value), ValueType::Int) => value.parse::<i32>().ok().map(Value::Int),
+            (&Value::String(ref value), ValueType::Float) => value.parse::<f32>().ok().map(Value::Float),
+            (_, ValueType::String) => match self {
+                &Value::Bool(_) | &Value::Int(_) | &Value::Float(_) => Some(Value::String(self.to_string())),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// Get the truthiness of the value when used as a condition: non-zero numbers, non-empty
+    /// strings and non-empty lists are true. Returns None for values which cannot be used as
+    /// conditions.
+    pub fn is_truthy(&self) -> Option<bool> {
+        match self {
+            &Value::String(ref value) => Some(!value.is_empty()),
+            &Value::List(ref value) => Some(!value.is_empty()),
+            _ => self.cast(ValueType::Bool).and_then(|value| value.as_bool())
+        }
+    }
+
+    /// When an empty list is assigned to a variable previously holding a list, keep the origins
+    /// of the previous list so that the variable still knows its list definitions.
+    pub fn retain_list_origins_for_assignment(&mut self, old_value: &Value) {
+        if let (&mut Value::List(ref mut new_list), &Value::List(ref old_list)) = (self, old_value) {
+            if new_list.is_empty() && new_list.origin_names().is_none() {
+                new_list.add_origin_names(old_list.all_origin_names());
+            }
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            &Value::Bool(value) => Some(value),
+            _ => None
+        }
+    }
+
     pub fn as_int(&self) -> Option<i32> {
         match self {
             &Value::Int(value) => Some(value),
@@ -27,6 +109,13 @@ impl Value {
         }
     }
 
+    pub fn as_list(&self) -> Option<&InkList> {
+        match self {
+            &Value::List(ref value) => Some(value),
+            _ => None
+        }
+    }
+
     pub fn as_string(&self) -> Option<&str> {
         match self {
             &Value::String(ref value) => Some(value),
@@ -38,11 +127,13 @@ impl Value {
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            &Value::Bool(value) => write!(f, "{}", value),
             &Value::Int(value) => write!(f, "{}", value),
             &Value::Float(value) => write!(f, "{}", value),
+            &Value::List(ref value) => write!(f, "{}", value),
             &Value::String(ref value) => write!(f, "{}", value),
-            &Value::DivertTarget(ref value) => write!(f, "DivertTarget({})", value.to_string()),
+            &Value::DivertTarget(ref value) => write!(f, "DivertTarget({})", value),
             &Value::VariablePointer(ref name, _) => write!(f, "VarPtr({})", name)
         }
     }
-}
\ No newline at end of file
+}
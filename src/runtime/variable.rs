@@ -1,6 +1,7 @@
 use std::fmt;
 use path::Path;
 
+#[derive(Clone)]
 pub struct VariableAssignment {
     name: String,
     is_new_declaration: bool,
@@ -39,6 +40,7 @@ impl fmt::Display for VariableAssignment {
     }
 }
 
+#[derive(Clone)]
 pub struct VariableReference {
     name: String
 }
@@ -61,6 +63,7 @@ impl fmt::Display for VariableReference {
     }
 }
 
+#[derive(Clone)]
 pub struct ReadCount {
     target: Path
 }
@@ -79,6 +82,6 @@ impl ReadCount {
 
 impl fmt::Display for ReadCount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "read_count({})", self.target.to_string())
+        write!(f, "read_count({})", self.target)
     }
 }
\ No newline at end of file
@@ -1,8 +1,16 @@
+use error::InkError;
+use json_parser::{read_bool, read_int, read_value};
+use json_writer::write_value;
+use path::{Path, Fragment};
 use runtime::RuntimeObject;
 use runtime::container::Container;
 use runtime::divert::PushPopType;
-use macros;
+use runtime::value::Value;
 
+use serde_json;
+use serde_json::Map;
+
+use std::collections::HashMap;
 use std::rc::Rc;
 
 #[derive(Clone)]
@@ -50,7 +58,11 @@ impl Element {
 pub struct RuntimeContext {
     stack: Vec<Element>,
     in_expression_evaluation: bool,
-    stack_push_type: PushPopType
+    stack_push_type: PushPopType,
+    temporary_variables: HashMap<String, Value>,
+    /// Length of the output stream when a function was called, used to trim the whitespace
+    /// output at the start and at the end of the function. None once some text has been output.
+    function_start_in_output_stream: Option<usize>
 }
 
 /// Depth-first search (pre-order) of the runtime graph implemented as a LIFO stack.
@@ -59,7 +71,9 @@ impl RuntimeContext {
         RuntimeContext {
             stack: vec![Element::new(container.clone())],
             in_expression_evaluation: false,
-            stack_push_type: PushPopType::Tunnel
+            stack_push_type: PushPopType::Tunnel,
+            temporary_variables: HashMap::new(),
+            function_start_in_output_stream: None
         }
     }
 
@@ -70,7 +84,9 @@ impl RuntimeContext {
         RuntimeContext {
             stack: stack,
             in_expression_evaluation: false,
-            stack_push_type: PushPopType::Tunnel
+            stack_push_type: PushPopType::Tunnel,
+            temporary_variables: HashMap::new(),
+            function_start_in_output_stream: None
         }
     }
 
@@ -94,6 +110,36 @@ impl RuntimeContext {
         self.stack_push_type = stack_push_type;
     }
 
+    /// Get the temporary variables declared in this context, keyed by name.
+    pub fn temporary_variables(&self) -> &HashMap<String, Value> {
+        &self.temporary_variables
+    }
+
+    pub fn temporary_variables_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.temporary_variables
+    }
+
+    pub fn function_start_in_output_stream(&self) -> Option<usize> {
+        self.function_start_in_output_stream
+    }
+
+    pub fn set_function_start_in_output_stream(&mut self, function_start_in_output_stream: Option<usize>) {
+        self.function_start_in_output_stream = function_start_in_output_stream;
+    }
+
+    /// Create the context of a tunnel or a function called from this context. The new context
+    /// starts at the same content pointer, out of expression evaluation and without temporary
+    /// variables.
+    pub fn fork(&self, stack_push_type: PushPopType, output_stream_length: usize) -> RuntimeContext {
+        RuntimeContext {
+            stack: self.stack.clone(),
+            in_expression_evaluation: false,
+            stack_push_type: stack_push_type,
+            temporary_variables: HashMap::new(),
+            function_start_in_output_stream: Some(output_stream_length)
+        }
+    }
+
     pub fn reset(&mut self, container: &Rc<Container>, index: usize) {
         let mut element = Element::new(container.clone());
 
@@ -105,6 +151,227 @@ impl RuntimeContext {
         self.stack.push(element);
     }
 
+    /// Clear the content pointer. The context then points to nothing until it is reset or moved
+    /// to a new path.
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Move the content pointer to the given absolute path, starting from the root container.
+    ///
+    /// If the path ends with an index the pointer is placed on that element, otherwise it is
+    /// placed at the start of the targeted container. Returns false if the path cannot be
+    /// resolved, in which case the pointer is left untouched.
+    pub fn move_to_path(&mut self, root_container: &Rc<Container>, path: &Path) -> bool {
+        if path.is_relative() {
+            return false;
+        }
+
+        let mut stack = vec![Element::new(root_container.clone())];
+        let len = path.len();
+
+        for (i, fragment) in path.iter().enumerate() {
+            let is_last = i + 1 == len;
+            let container = stack.last().unwrap().container.clone();
+
+            let child = match fragment {
+                &Fragment::Index(index) => {
+                    if !stack.last_mut().unwrap().move_to(index) {
+                        return false;
+                    }
+
+                    if is_last {
+                        break;
+                    }
+
+                    match container.get(index) {
+                        Some(&RuntimeObject::Container(ref child)) => child.clone(),
+                        _ => return false
+                    }
+                },
+                &Fragment::Name(ref name) => {
+                    // Named-only content has no index in its parent, leave the parent element
+                    // past its end so that stepping out of the child stops the flow.
+                    let index = container.index_of_name(name).unwrap_or(container.len());
+                    stack.last_mut().unwrap().index = index;
+
+                    match container.search_by_name(name) {
+                        Some(&RuntimeObject::Container(ref child)) => child.clone(),
+                        _ => return false
+                    }
+                }
+            };
+
+            stack.push(Element::new(child));
+        }
+
+        self.stack = stack;
+        true
+    }
+
+    /// Write the content pointer and the state of the context in the JSON format of the story
+    /// state: the path of the current container and the index in it, which are omitted when the
+    /// pointer is cleared.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = Map::new();
+
+        if let Some((_, index, path)) = self.ancestry().pop() {
+            json.insert("cPath".to_owned(), serde_json::Value::String(path.to_string()));
+            json.insert("idx".to_owned(), serde_json::Value::Number(index.into()));
+        }
+
+        let stack_push_type = match self.stack_push_type {
+            PushPopType::Function => 1,
+            PushPopType::FunctionEvaluationFromGame => 2,
+            _ => 0
+        };
+
+        json.insert("exp".to_owned(), serde_json::Value::Bool(self.in_expression_evaluation));
+        json.insert("type".to_owned(), serde_json::Value::Number(stack_push_type.into()));
+
+        if !self.temporary_variables.is_empty() {
+            let temporary_variables = self.temporary_variables.iter()
+                .map(|(name, value)| (name.clone(), write_value(value)))
+                .collect();
+
+            json.insert("temp".to_owned(), serde_json::Value::Object(temporary_variables));
+        }
+
+        serde_json::Value::Object(json)
+    }
+
+    /// Read a context written by `to_json`, its content pointer is moved in the given root
+    /// container.
+    pub fn from_json(json: &serde_json::Value, root_container: &Rc<Container>) -> Result<RuntimeContext, InkError> {
+        let mut runtime_context = RuntimeContext::new(root_container);
+
+        match json.get("cPath").and_then(|container_path| container_path.as_str()) {
+            Some(container_path) => {
+                // The root container has an empty path
+                let mut fragments: Vec<Fragment> = match Path::from_str(container_path) {
+                    Some(path) => path.iter().cloned().collect(),
+                    _ => Vec::new()
+                };
+
+                // A negative index points to the container itself
+                let index = read_int(json, "idx")?;
+                if index >= 0 {
+                    fragments.push(Fragment::Index(index as usize));
+                }
+
+                let path = Path::from_fragments(fragments, false);
+                if !runtime_context.move_to_path(root_container, &path) {
+                    return Err(InkError::from_message(format!("When loading state, internal story location couldn't be found: {}", path)));
+                }
+            },
+            _ => runtime_context.clear()
+        }
+
+        runtime_context.in_expression_evaluation = read_bool(json, "exp")?;
+        runtime_context.stack_push_type = match read_int(json, "type")? {
+            0 => PushPopType::Tunnel,
+            1 => PushPopType::Function,
+            2 => PushPopType::FunctionEvaluationFromGame,
+            stack_push_type => return Err(InkError::from_message(format!("Unknown callstack element type {} in the story state", stack_push_type)))
+        };
+
+        if let Some(temporary_variables) = json.get("temp").and_then(|temporary_variables| temporary_variables.as_object()) {
+            for (name, value) in temporary_variables {
+                runtime_context.temporary_variables.insert(name.clone(), read_value(value)?);
+            }
+        }
+
+        Ok(runtime_context)
+    }
+
+    /// Get the containers of the stack, from the root container to the current container.
+    pub fn containers(&self) -> Vec<Rc<Container>> {
+        self.stack.iter().map(|element| element.container.clone()).collect()
+    }
+
+    /// Get the containers of the stack, from the root container to the current container, along
+    /// with the index of the content pointer in each of them and their path. A named container
+    /// is identified by its name in the path, other containers by their index in their parent.
+    pub fn ancestry(&self) -> Vec<(Rc<Container>, usize, Path)> {
+        let mut ancestry = Vec::with_capacity(self.stack.len());
+        let mut fragments = Vec::with_capacity(self.stack.len());
+
+        for (i, element) in self.stack.iter().enumerate() {
+            if i > 0 {
+                fragments.push(match element.container.name() {
+                    Some(name) => Fragment::Name(name.to_owned()),
+                    _ => Fragment::Index(self.stack[i - 1].index)
+                });
+            }
+
+            ancestry.push((element.container.clone(), element.index, Path::from_fragments(fragments.clone(), false)));
+        }
+
+        ancestry
+    }
+
+    /// Get the path of the current container.
+    pub fn container_path(&self) -> Option<Path> {
+        self.ancestry().pop().map(|(_, _, path)| path)
+    }
+
+    /// Get the container and the index of the current content pointer.
+    pub fn pointer(&self) -> Option<(Rc<Container>, usize)> {
+        self.stack.last().map(|element| (element.container.clone(), element.index))
+    }
+
+    /// Step into the container under the content pointer, placing the pointer on its first
+    /// element. Returns the entered container, or None if the current element is not a container
+    /// or if the container is empty.
+    pub fn enter_container(&mut self) -> Option<Rc<Container>> {
+        let container = match self.get() {
+            Some(&RuntimeObject::Container(ref container)) if !container.is_empty() => container.clone(),
+            _ => return None
+        };
+
+        self.stack.push(Element::new(container.clone()));
+        Some(container)
+    }
+
+    /// Move the content pointer to the next element, stepping out of the containers that have
+    /// been fully visited. Stepping out of a container which is not part of its parent indexed
+    /// content (named-only content) is not possible: the pointer is then cleared and false is
+    /// returned.
+    pub fn increment(&mut self) -> bool {
+        loop {
+            match self.stack.last_mut() {
+                Some(element) => {
+                    element.index += 1;
+
+                    if element.index < element.container.len() {
+                        return true;
+                    }
+                },
+                None => return false
+            }
+
+            if self.stack.len() == 1 {
+                self.stack.clear();
+                return false;
+            }
+
+            let child = self.stack.pop().unwrap().container;
+            let is_indexed = match self.get() {
+                Some(&RuntimeObject::Container(ref container)) => Rc::ptr_eq(container, &child),
+                _ => false
+            };
+
+            if !is_indexed {
+                self.stack.clear();
+                return false;
+            }
+        }
+    }
+
     pub fn get(&self) -> Option<&RuntimeObject> {
         try_opt!(self.stack.last()).get()
     }
@@ -131,7 +398,7 @@ impl RuntimeContext {
                 Some(&RuntimeObject::Container(ref container)) => {
                     next_container = Some(container.clone());
                 },
-                Some(runtime_object) => return true,
+                Some(_) => return true,
                 None => {}
             }
         }
@@ -203,9 +470,7 @@ mod tests {
 
     #[test]
     fn empty_container_test() {
-        use runtime::value::Value;
-
-        let mut root_container = Rc::new(Container::new());
+        let root_container = Rc::new(Container::new());
         let mut runtime_context = RuntimeContext::new(&root_container);
 
         assert!(runtime_context.get().is_none());
@@ -1,18 +1,21 @@
 use path::{Path, Fragment};
 use runtime::container::Container;
+use runtime::list_definition::ListDefinitions;
 use runtime::RuntimeObject;
 use std::rc::Rc;
 
 pub struct RuntimeGraph {
     ink_version: u32,
-    root_container: Rc<Container>
+    root_container: Rc<Container>,
+    list_definitions: ListDefinitions
 }
 
 impl RuntimeGraph {
     pub fn new(ink_version: u32, container: Rc<Container>) -> RuntimeGraph {
         RuntimeGraph {
             ink_version: ink_version,
-            root_container: container
+            root_container: container,
+            list_definitions: ListDefinitions::new()
         }
     }
 
@@ -24,12 +27,45 @@ impl RuntimeGraph {
         &self.root_container
     }
 
+    pub fn list_definitions(&self) -> &ListDefinitions {
+        &self.list_definitions
+    }
+
+    pub fn set_list_definitions(&mut self, list_definitions: ListDefinitions) {
+        self.list_definitions = list_definitions;
+    }
+
+    /// Resolve a path leading to a container, returns the container along with its canonical
+    /// path: a named container is identified by its name, other containers by their index.
+    pub fn resolve_container_path(&self, path: &Path) -> Option<(&Rc<Container>, Path)> {
+        let mut current_container = &self.root_container;
+        let mut fragments = Vec::with_capacity(path.len());
+
+        for fragment in path.iter() {
+            let child = match fragment {
+                &Fragment::Index(index) => current_container.get(index),
+                &Fragment::Name(ref name) => current_container.search_by_name(name)
+            };
+
+            current_container = match child {
+                Some(&RuntimeObject::Container(ref container)) => container,
+                _ => return None
+            };
+
+            fragments.push(match current_container.name() {
+                Some(name) => Fragment::Name(name.to_owned()),
+                _ => fragment.clone()
+            });
+        }
+
+        Some((current_container, Path::from_fragments(fragments, false)))
+    }
+
     pub fn resolve_path(&self, path: &Path) -> Option<&RuntimeObject> {
         let mut current_container = &self.root_container;
         let mut runtime_object: Option<&RuntimeObject> = None;
 
-        let mut it = path.iter();
-        while let Some(fragment) = it.next() {
+        for fragment in path.iter() {
             match fragment {
                 &Fragment::Index(index) => {
                     match current_container.get(index) {
@@ -84,7 +120,7 @@ mod tests {
 
         child_level_2.add_child(RuntimeObject::Container(Rc::new(child_level_3)));
         child_level_1.add_child(RuntimeObject::Container(Rc::new(child_level_2)));
-        root_container.add_child(RuntimeObject::Container(Rc::new((child_level_1))));
+        root_container.add_child(RuntimeObject::Container(Rc::new(child_level_1)));
 
         let graph = RuntimeGraph::new(17, Rc::new(root_container));
 
@@ -96,6 +132,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn resolve_container_path_test() {
+        let mut root_container = Container::new();
+
+        let mut knot = Container::new();
+        knot.set_name("knot".to_owned());
+
+        let mut gather = Container::new();
+        gather.set_name("g-0".to_owned());
+
+        let mut sub_container = Container::new();
+        sub_container.add_child(RuntimeObject::Container(Rc::new(gather)));
+        knot.add_child(RuntimeObject::Container(Rc::new(sub_container)));
+        root_container.add_named_child("knot".to_owned(), Rc::new(knot));
+
+        let graph = RuntimeGraph::new(17, Rc::new(root_container));
+
+        let (container, path) = graph.resolve_container_path(&Path::from_str("knot.0.0").unwrap()).unwrap();
+        assert_eq!(container.name(), Some("g-0"));
+        assert_eq!(path.to_string(), "knot.0.g-0");
+
+        assert!(graph.resolve_container_path(&Path::from_str("knot.1").unwrap()).is_none());
+    }
+
     #[test]
     fn resolve_path_by_index_test() {
         use runtime::divert::{Divert, TargetType};
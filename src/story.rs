@@ -1,26 +1,56 @@
 use choice::Choice;
-use error::{InkError, InkErrorCode};
+use callstack::Thread;
+use error::InkError;
+use external_function::{ExternalFunction, ExternalFunctionCallback};
 use json_parser::RuntimeGraphBuilder;
+use path::{Fragment, Path};
+use prng::Prng;
+use runtime::RuntimeObject;
+use runtime::choice_point::ChoicePoint;
+use runtime::container::Container;
+use runtime::control_command::ControlCommand;
+use runtime::divert::{Divert, PushPopType, TargetType};
+use runtime::ink_list::InkList;
+use runtime::native_function_call::NativeFunctionCall;
+use runtime::tag::Tag;
+use runtime::value::{Value, ValueType};
+use runtime_context::RuntimeContext;
 use runtime_graph::RuntimeGraph;
-use story_state::StoryState;
+use story_state::{StoryState, clean_output_whitespace};
+use variable_observer::{VariableObserver, VariableObserverCallback, VariableObserverHandle};
 
+use serde_json;
+
+use std::collections::HashMap;
 use std::io::Read;
+use std::rc::Rc;
 
-pub const InkVersion: u32 = 17;
-pub const InkVersionMinimumCompatible: u32 = 16;
+pub const INK_VERSION: u32 = 21;
+pub const INK_VERSION_MINIMUM_COMPATIBLE: u32 = 16;
 
 pub struct Story {
     runtime_graph: RuntimeGraph,
-    state: StoryState
+    state: StoryState,
+    current_text: Option<String>,
+    current_tags: Vec<String>,
+    variable_observers: Vec<VariableObserver>,
+    next_variable_observer_handle: usize,
+    external_functions: HashMap<String, ExternalFunction>,
+    allow_external_function_fallbacks: bool,
+    state_snapshot_at_last_newline: Option<StoryState>,
+    saw_lookahead_unsafe_function_after_newline: bool
 }
 
-enum StoryFlow<'a> {
+#[derive(Debug, PartialEq)]
+pub enum StoryFlow<'a> {
     Continue(&'a str),
     WaitForChoice,
     End
 }
 
 impl Story {
+    // Public API of the crate, kept as is rather than implementing FromStr
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Story, InkError> {
         Story::new( RuntimeGraphBuilder::from_str(s)?)
     }
@@ -36,34 +66,391 @@ impl Story {
     }
 
     fn new(runtime_graph: RuntimeGraph) -> Result<Story, InkError> {
-        if runtime_graph.ink_version() > InkVersion {
-            return Err(InkError::new(InkErrorCode::Message("Version of ink used to build story is newer than the current version of the engine".to_owned())));
+        if runtime_graph.ink_version() > INK_VERSION {
+            return Err(InkError::from_message("Version of ink used to build story is newer than the current version of the engine"));
         }
-        else if runtime_graph.ink_version() < InkVersionMinimumCompatible {
-            return Err(InkError::new(InkErrorCode::Message("Version of ink used to build story is too old to be loaded by this version of the engine".to_owned())));
+        else if runtime_graph.ink_version() < INK_VERSION_MINIMUM_COMPATIBLE {
+            return Err(InkError::from_message("Version of ink used to build story is too old to be loaded by this version of the engine"));
         }
 
         let state = StoryState::new(&runtime_graph);
 
-        Ok(Story {
+        let mut story = Story {
             runtime_graph: runtime_graph,
-            state: state
-        })
+            state: state,
+            current_text: None,
+            current_tags: Vec::new(),
+            variable_observers: Vec::new(),
+            next_variable_observer_handle: 0,
+            external_functions: HashMap::new(),
+            allow_external_function_fallbacks: false,
+            state_snapshot_at_last_newline: None,
+            saw_lookahead_unsafe_function_after_newline: false
+        };
+
+        story.reset_globals()?;
+        Ok(story)
+    }
+
+    /// Initialise the global variables by running the "global decl" container of the story, if
+    /// any, then keep their values as the default globals.
+    fn reset_globals(&mut self) -> Result<(), InkError> {
+        let has_global_declaration = match self.runtime_graph.root_container().search_by_name("global decl") {
+            Some(&RuntimeObject::Container(_)) => true,
+            _ => false
+        };
+
+        if has_global_declaration {
+            let original_callstack = self.state.callstack().clone();
+
+            self.divert_to_path(&Path::from_str("global decl").unwrap())?;
+            while !self.state.end_of_story() {
+                self.step()?;
+            }
+
+            *self.state.callstack() = original_callstack;
+            self.state.reset_output();
+        }
+
+        self.state.variables_state_mut().snapshot_default_globals();
+        Ok(())
+    }
+
+    /// Get the value of a global variable declared in the story.
+    pub fn variable(&self, name: &str) -> Result<Value, InkError> {
+        match self.state.variables_state().global_variable(name) {
+            Some(value) => Ok(value.clone()),
+            _ => Err(InkError::from_message(format!("Variable {} has not been declared in the story", name)))
+        }
+    }
+
+    /// Set the value of a global variable declared in the story.
+    ///
+    /// The value must have the type of the variable, an integer is accepted for a float variable.
+    /// List values can only contain items from the lists defined in the story.
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<(), InkError> {
+        let value_type = match self.state.variables_state().global_variable(name) {
+            Some(old_value) => old_value.value_type(),
+            _ => return Err(InkError::from_message(format!("Cannot assign to a variable ({}) that hasn't been declared in the story", name)))
+        };
+
+        let value = match (value.value_type(), value_type) {
+            (ValueType::VariablePointer, _) => None,
+            (new_value_type, value_type) if new_value_type == value_type => Some(value.clone()),
+            (ValueType::Int, ValueType::Float) => value.cast(ValueType::Float),
+            _ => None
+        }.ok_or_else(|| {
+            InkError::from_message(format!("Cannot assign a value of type {:?} to variable {} of type {:?}", value.value_type(), name, value_type))
+        })?;
+
+        if let Value::List(ref ink_list) = value {
+            let list_definitions = self.runtime_graph.list_definitions();
+
+            for (item, &item_value) in ink_list.list().iter() {
+                let known_item = item.origin_name()
+                    .and_then(|origin_name| list_definitions.list_definition(origin_name))
+                    .and_then(|list_definition| list_definition.value_for_item(item));
+
+                if known_item != Some(item_value) {
+                    return Err(InkError::from_message(format!("Cannot assign to variable {} a list containing {} which is not an item of the lists defined in the story", name, item)));
+                }
+            }
+        }
+
+        self.state.variables_state_mut().set_global(name, value);
+        self.notify_variable_observers();
+        Ok(())
+    }
+
+    /// Register a callback called with the new value of the given global variable whenever it
+    /// changes. Changes made by the story are notified once `advance` has completed its step.
+    pub fn observe_variable<F>(&mut self, name: &str, callback: F) -> VariableObserverHandle
+        where F: FnMut(&str, &Value) + 'static {
+        self.add_variable_observer(Some(name.to_owned()), Box::new(callback))
+    }
+
+    /// Register a callback called with the name and the new value of any global variable that
+    /// changes, see `observe_variable`.
+    pub fn observe_variables<F>(&mut self, callback: F) -> VariableObserverHandle
+        where F: FnMut(&str, &Value) + 'static {
+        self.add_variable_observer(None, Box::new(callback))
+    }
+
+    /// Remove a variable observer, returns false if there is no observer with this handle.
+    pub fn remove_variable_observer(&mut self, handle: VariableObserverHandle) -> bool {
+        let len = self.variable_observers.len();
+        self.variable_observers.retain(|observer| observer.handle() != handle);
+        self.variable_observers.len() != len
+    }
+
+    fn add_variable_observer(&mut self, name: Option<String>, callback: VariableObserverCallback) -> VariableObserverHandle {
+        let observer = VariableObserver::new(self.next_variable_observer_handle, name, callback);
+        let handle = observer.handle();
+
+        self.next_variable_observer_handle += 1;
+        self.variable_observers.push(observer);
+        handle
+    }
+
+    /// Call the observers of the globals which have changed since the last notification.
+    fn notify_variable_observers(&mut self) {
+        for name in self.state.variables_state_mut().take_changed_variables() {
+            let value = match self.state.variables_state().global_variable(&name) {
+                Some(value) => value.clone(),
+                _ => continue
+            };
+
+            for observer in self.variable_observers.iter_mut().filter(|observer| observer.observes(&name)) {
+                observer.notify(&name, &value);
+            }
+        }
+    }
+
+    /// Bind a function of the game to an EXTERNAL function declared in ink. The function is
+    /// called with the arguments given in ink, and may return a value to ink.
+    ///
+    /// When the story reaches a newline it keeps evaluating ahead to check that the line is
+    /// really over. A function which is not lookahead safe, for instance because it has side
+    /// effects in the game, ends the line instead of being called during this lookahead.
+    ///
+    /// # Errors
+    ///
+    /// This fails if a function is already bound to this name.
+    pub fn bind_external_function<F>(&mut self, name: &str, function: F, lookahead_safe: bool) -> Result<(), InkError>
+        where F: FnMut(&[Value]) -> Option<Value> + 'static {
+        if self.external_functions.contains_key(name) {
+            return Err(InkError::from_message(format!("Function '{}' has already been bound.", name)));
+        }
+
+        let function: ExternalFunctionCallback = Box::new(function);
+        self.external_functions.insert(name.to_owned(), ExternalFunction::new(function, lookahead_safe));
+        Ok(())
+    }
+
+    /// Remove the function bound to an EXTERNAL function.
+    ///
+    /// # Errors
+    ///
+    /// This fails if no function is bound to this name.
+    pub fn unbind_external_function(&mut self, name: &str) -> Result<(), InkError> {
+        match self.external_functions.remove(name) {
+            Some(_) => Ok(()),
+            _ => Err(InkError::from_message(format!("Function '{}' has not been bound.", name)))
+        }
+    }
+
+    /// Whether an EXTERNAL function which has not been bound falls back to the ink function of
+    /// the same name. This is disabled by default, calling an unbound function is then an error.
+    pub fn allow_external_function_fallbacks(&self) -> bool {
+        self.allow_external_function_fallbacks
+    }
+
+    pub fn set_allow_external_function_fallbacks(&mut self, allow_external_function_fallbacks: bool) {
+        self.allow_external_function_fallbacks = allow_external_function_fallbacks;
     }
 
     /// The list of Choice objects available at the current point in
     /// the Story.
-    pub fn current_choices(&self) -> Option<&Vec<Choice>> {
-        None
+    pub fn current_choices(&self) -> Option<Vec<&Choice>> {
+        let choices = self.state.visible_choices();
+        if choices.is_empty() {
+            return None;
+        }
+
+        Some(choices)
+    }
+
+    /// The invisible default choice which was automatically followed during the last call to
+    /// `advance`, if any. This is meant for debugging, to see when a fallback choice fired.
+    pub fn followed_default_choice(&self) -> Option<&Choice> {
+        self.state.followed_default_choice()
     }
 
     /// The latest line of content.
     pub fn current_text(&self) -> Option<&str> {
-        None
+        self.current_text.as_deref()
+    }
+
+    /// The tags of the latest line of content.
+    pub fn current_tags(&self) -> &Vec<String> {
+        &self.current_tags
+    }
+
+    /// The tags at the very top of the story, before any content.
+    pub fn global_tags(&self) -> Vec<String> {
+        tags_at_start_of_container(self.runtime_graph.root_container())
+    }
+
+    /// The tags at the start of the knot or stitch at the given path, before any content.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the path does not lead to a knot or a stitch.
+    pub fn tags_for_content_at_path(&self, path: &str) -> Result<Vec<String>, InkError> {
+        let container = match Path::from_str(path).and_then(|path| self.runtime_graph.resolve_path(&path)) {
+            Some(&RuntimeObject::Container(ref container)) => container,
+            _ => return Err(InkError::from_message(format!("Content at path {} not found", path)))
+        };
+
+        Ok(tags_at_start_of_container(container))
+    }
+
+    /// Get the number of times the knot, stitch or other counted container at the given path has
+    /// been visited.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the path does not lead to a container.
+    pub fn visit_count_at_path(&self, path: &str) -> Result<i32, InkError> {
+        match Path::from_str(path).and_then(|path| self.runtime_graph.resolve_container_path(&path)) {
+            Some((_, path)) => Ok(self.state.visit_count_at_path(&path.to_string())),
+            _ => Err(InkError::from_message(format!("No knot or stitch at path {}", path)))
+        }
+    }
+
+    /// Evaluate an ink function with the given arguments, without affecting the current line and
+    /// choices. Returns the value returned by the function, if any, and the text it output.
+    ///
+    /// Compiled stories don't tell functions apart from other knots, a knot which is not a
+    /// function is only detected when its evaluation ends the story instead of returning.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there is no knot or stitch at the given path, if the evaluation ends the
+    /// story instead of returning, or if an error occur during the evaluation of the function.
+    /// The current flow is left as it was before the evaluation, so that the story can carry on.
+    pub fn evaluate_function(&mut self, name: &str, arguments: &[Value]) -> Result<(Option<Value>, String), InkError> {
+        let path = match Path::from_str(name).and_then(|path| self.runtime_graph.resolve_container_path(&path)) {
+            Some((_, path)) => path,
+            _ => match Path::from_str(name).and_then(|path| self.runtime_graph.resolve_path(&path)) {
+                Some(_) => return Err(InkError::from_message(format!("Content at '{}' is not a function", name))),
+                _ => return Err(InkError::from_message(format!("Function doesn't exist: '{}'", name)))
+            }
+        };
+
+        // The evaluation happens in a new callstack element on top of the current flow, which
+        // is left untouched, along with its output and choices
+        let original_callstack = self.state.callstack().clone();
+        let output_stream = self.state.output_stream().clone();
+        let choices = self.state.choices().clone();
+        let evaluation_stack_height = self.state.evaluation_stack().len();
+
+        self.state.reset_output();
+        let result = self.evaluate_function_at_path(name, &path, arguments, evaluation_stack_height);
+
+        // Restore the flow whether the evaluation succeeded or not, an -> END in particular
+        // unwinds the whole callstack
+        *self.state.callstack() = original_callstack;
+        self.state.truncate_evaluation_stack(evaluation_stack_height);
+        self.state.set_output_stream(output_stream);
+        self.state.set_choices(choices);
+        self.notify_variable_observers();
+
+        let (returned_object, text) = result?;
+
+        match returned_object {
+            Some(RuntimeObject::Value(value)) => Ok((Some(value), text)),
+            Some(RuntimeObject::Void) | None => Ok((None, text)),
+            Some(runtime_object) => Err(InkError::from_message(format!("Unexpected object returned by function '{}': {}", name, runtime_object)))
+        }
+    }
+
+    /// Run the function at the given path in a new callstack element, returning the topmost
+    /// object it left on the evaluation stack and the text it output. The caller restores the
+    /// callstack and the evaluation stack afterwards.
+    fn evaluate_function_at_path(&mut self, name: &str, path: &Path, arguments: &[Value], evaluation_stack_height: usize) -> Result<(Option<RuntimeObject>, String), InkError> {
+        self.state.callstack().push(PushPopType::FunctionEvaluationFromGame, 0)?;
+        self.divert_to_path(path)?;
+
+        for argument in arguments {
+            self.state.push_evaluation_stack(RuntimeObject::Value(argument.clone()));
+        }
+
+        let mut text = String::new();
+
+        while !self.state.end_of_story() {
+            self.state.reset_output();
+            self.continue_internal()?;
+            text.push_str(&self.state.current_text());
+        }
+
+        if self.state.callstack().current_stack_push_type() != Some(PushPopType::FunctionEvaluationFromGame) {
+            return Err(InkError::from_message(format!("Evaluation of '{}' ended the story instead of returning, it may not be a function", name)));
+        }
+
+        // The returned value is the topmost one, the function may have left other objects
+        let returned_object = if self.state.evaluation_stack().len() > evaluation_stack_height {
+            self.state.pop_evaluation_stack()
+        } else {
+            None
+        };
+
+        Ok((returned_object, text))
+    }
+
+    /// Save the state of the story as JSON, in the format of the reference ink runtime so that
+    /// the state can be loaded by other ink runtimes as well.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the state contains content that cannot be saved.
+    pub fn save_state(&self) -> Result<String, InkError> {
+        Ok(self.state.to_json()?.to_string())
+    }
+
+    /// Load a state saved by `save_state`, or by the reference ink runtime. The story then
+    /// resumes where it was when it was saved.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the JSON is not a valid state for this story, the state of the story is
+    /// then left untouched.
+    pub fn load_state(&mut self, json: &str) -> Result<(), InkError> {
+        let json: serde_json::Value = serde_json::from_str(json)?;
+        self.state.load_json(&json, &self.runtime_graph)?;
+        self.update_current_output();
+
+        Ok(())
+    }
+
+    /// Get the name of the current flow, see `switch_flow`.
+    pub fn current_flow_name(&self) -> &str {
+        self.state.current_flow_name()
+    }
+
+    /// Get the names of the flows which have been started and not removed, except the default
+    /// flow.
+    pub fn alive_flow_names(&self) -> Vec<String> {
+        self.state.alive_flow_names()
+    }
+
+    /// Switch to another flow of the story. Each flow has its own position in the story, output
+    /// and choices, so that several conversations can run in parallel, while the globals and the
+    /// visit counts are shared. A new flow starts at the root of the story, use `choose_path` to
+    /// move it elsewhere.
+    pub fn switch_flow(&mut self, name: &str) {
+        self.state.switch_flow(name, self.runtime_graph.root_container());
+        self.update_current_output();
     }
 
-    pub fn current_tags(&self) -> Option<&Vec<&str>> {
-        None
+    /// Remove a flow, the story switches back to the default flow if it is the current flow.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the given flow is the default flow, which cannot be removed.
+    pub fn remove_flow(&mut self, name: &str) -> Result<(), InkError> {
+        self.state.remove_flow(name, self.runtime_graph.root_container())?;
+        self.update_current_output();
+
+        Ok(())
+    }
+
+    /// Get the current text and tags from the output stream, after the state has been replaced.
+    fn update_current_output(&mut self) {
+        let text = self.state.current_text();
+        self.current_text = if text.is_empty() { None } else { Some(text) };
+        self.current_tags = self.state.current_tags();
+        self.state_snapshot_at_last_newline = None;
     }
 
     /// Continue the story for one line of content, if possible.
@@ -75,12 +462,2104 @@ impl Story {
     /// # Errors
     ///
     /// This can fail if an error occur during the evaluation of the Story.
-    pub fn advance(&self) -> Result<StoryFlow, InkError> {
+    pub fn advance(&mut self) -> Result<StoryFlow<'_>, InkError> {
+        self.state.reset_output();
+        self.current_text = None;
+        self.current_tags.clear();
+        self.state.clear_followed_default_choice();
+
+        // Variable observers are only notified once the step is over
+        let result = self.continue_internal();
+        self.notify_variable_observers();
+        result?;
+
+        self.current_tags = self.state.current_tags();
+
+        let text = self.state.current_text();
+        if !text.is_empty() {
+            self.current_text = Some(text);
+            return Ok(StoryFlow::Continue(self.current_text.as_ref().unwrap()));
+        }
+
+        if !self.state.visible_choices().is_empty() {
+            return Ok(StoryFlow::WaitForChoice);
+        }
+
         Ok(StoryFlow::End)
     }
 
-    pub fn make_choice(&self, index: usize) -> bool {
-        return false;
+    /// Step through the story until a full line of content has been output.
+    ///
+    /// Once a newline has been output the story keeps evaluating ahead, since the newline may
+    /// still be removed by glue. The state at the newline is restored as soon as some content
+    /// proves that the line is really over.
+    fn continue_internal(&mut self) -> Result<(), InkError> {
+        self.state_snapshot_at_last_newline = None;
+        self.saw_lookahead_unsafe_function_after_newline = false;
+
+        if self.state.end_of_story() && !self.in_function_evaluation_from_game() {
+            self.try_follow_default_invisible_choice()?;
+        }
+
+        // Nothing left to run, the end of the flow has already been checked
+        if self.state.end_of_story() {
+            return Ok(());
+        }
+
+        self.state.set_did_safe_exit(false);
+
+        while !self.state.end_of_story() {
+            if self.continue_single_step()? {
+                break;
+            }
+        }
+
+        if let Some(state) = self.state_snapshot_at_last_newline.take() {
+            self.state = state;
+        }
+
+        if self.state.end_of_story() {
+            self.check_end_of_content()?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the flow stopped on purpose once the content pointer has run out: by a DONE
+    /// or an END, at a choice or at the end of a function called by the game.
+    fn check_end_of_content(&mut self) -> Result<(), InkError> {
+        if !self.state.choices().is_empty() || self.state.did_safe_exit() || self.in_function_evaluation_from_game() {
+            return Ok(());
+        }
+
+        let message = if self.state.callstack().can_pop(PushPopType::Tunnel) {
+            "unexpectedly reached end of content. Do you need a '->->' to return from a tunnel?"
+        } else if self.state.callstack().can_pop(PushPopType::Function) {
+            "unexpectedly reached end of content. Do you need a '~ return'?"
+        } else if self.state.callstack().depth() <= 1 {
+            "ran out of content. Do you need a '-> DONE' or '-> END'?"
+        } else {
+            "unexpectedly reached end of content for unknown reason."
+        };
+
+        Err(InkError::from_message(message))
+    }
+
+    /// Perform a single step, returns true once a full line of content has been output.
+    fn continue_single_step(&mut self) -> Result<bool, InkError> {
+        self.step()?;
+
+        // Out of content, follow the fallback choice if it is the only kind of choice left
+        if self.state.end_of_story() && !self.in_function_evaluation_from_game() {
+            self.try_follow_default_invisible_choice()?;
+        }
+
+        // Don't rewind during string evaluation, used for instance by choices
+        if self.state.in_string_evaluation() {
+            return Ok(false);
+        }
+
+        // A newline was previously output, check whether it really is the end of the line
+        let change = match self.state_snapshot_at_last_newline {
+            Some(ref snapshot) => Some(output_state_change(&snapshot.current_text(), &self.state.current_text(),
+                                                           snapshot.current_tags().len(), self.state.current_tags().len())),
+            _ => None
+        };
+
+        match change {
+            Some(OutputStateChange::ExtendedBeyondNewline) => {
+                self.state = self.state_snapshot_at_last_newline.take().unwrap();
+                return Ok(true);
+            },
+            // An external function which must not be called during the lookahead ends the line
+            Some(_) if self.saw_lookahead_unsafe_function_after_newline => {
+                self.state = self.state_snapshot_at_last_newline.take().unwrap();
+                return Ok(true);
+            },
+            // The newline is no longer valid, for instance it was removed by glue
+            Some(OutputStateChange::NewlineRemoved) => self.state_snapshot_at_last_newline = None,
+            _ => {}
+        }
+
+        if self.state.output_stream_ends_in_newline() {
+            if self.state.end_of_story() {
+                self.state_snapshot_at_last_newline = None;
+            } else if self.state_snapshot_at_last_newline.is_none() {
+                self.state_snapshot_at_last_newline = Some(self.state.clone());
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Whether a function called by the game is being evaluated in the current callstack element.
+    fn in_function_evaluation_from_game(&mut self) -> bool {
+        self.state.callstack().current_stack_push_type() == Some(PushPopType::FunctionEvaluationFromGame)
+    }
+
+    /// Follow the first invisible default choice when all the current choices are invisible
+    /// defaults. Returns false if there is no such choice to follow.
+    fn try_follow_default_invisible_choice(&mut self) -> Result<bool, InkError> {
+        let choice = match self.state.choices().first() {
+            Some(choice) if self.state.visible_choices().is_empty() => choice.clone(),
+            _ => return Ok(false)
+        };
+
+        let path = match choice.path_on_choice() {
+            Some(path) => path.clone(),
+            _ => return Err(InkError::from_message("Choice has no target"))
+        };
+
+        self.state.callstack().set_thread(choice.thread().clone());
+        self.choose_path_internal(&path, false)?;
+        self.state.set_followed_default_choice(choice);
+
+        Ok(true)
+    }
+
+    /// Choose one of the current choices, the story then resumes from the choice's target when
+    /// `advance` is called.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the index does not match any of the current choices.
+    pub fn make_choice(&mut self, index: usize) -> Result<(), InkError> {
+        let (path, thread) = match self.state.visible_choices().get(index) {
+            Some(choice) => match choice.path_on_choice() {
+                Some(path) => (path.clone(), choice.thread().clone()),
+                _ => return Err(InkError::from_message("Choice has no target"))
+            },
+            _ => return Err(InkError::from_message(format!("Choice index {} out of range, there are {} choices", index, self.state.visible_choices().len())))
+        };
+
+        self.state.callstack().set_thread(thread);
+        self.choose_path_internal(&path, true)
+    }
+
+    /// Jump to the knot or stitch at the given path, e.g. "knot" or "knot.stitch", the story then
+    /// resumes from there when `advance` is called. The callstack is reset and the current choices
+    /// are dropped. The arguments are passed to the parameters of the knot, if any.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there is no content at the given path.
+    pub fn choose_path(&mut self, path: &str, arguments: &[Value]) -> Result<(), InkError> {
+        let path = match Path::from_str(path) {
+            Some(ref path) if self.runtime_graph.resolve_path(path).is_some() => path.clone(),
+            _ => return Err(InkError::from_message(format!("Failed to find content at path: {}", path)))
+        };
+
+        let mut thread = Thread::new();
+        thread.push(RuntimeContext::new(self.runtime_graph.root_container()));
+        self.state.callstack().reset(thread);
+
+        for argument in arguments {
+            self.state.push_evaluation_stack(RuntimeObject::Value(argument.clone()));
+        }
+
+        self.choose_path_internal(&path, true)
+    }
+
+    /// Move the story to the given path, dropping the current choices. A new turn starts when
+    /// the path is chosen by the player.
+    fn choose_path_internal(&mut self, path: &Path, incrementing_turn_index: bool) -> Result<(), InkError> {
+        self.state.clear_choices();
+
+        if incrementing_turn_index {
+            self.state.increment_turn_index();
+        }
+
+        self.divert_to_path(path)
+    }
+
+    /// Evaluate the runtime object under the content pointer and move to the next one.
+    fn step(&mut self) -> Result<(), InkError> {
+        let mut entered_containers = Vec::new();
+
+        let (container, index) = {
+            let runtime_context = match self.state.callstack().runtime_context_mut() {
+                Some(runtime_context) => runtime_context,
+                _ => return Ok(())
+            };
+
+            // Step directly to the first element of content in a container
+            while let Some(container) = runtime_context.enter_container() {
+                if let Some(path) = runtime_context.container_path() {
+                    entered_containers.push((container, path));
+                }
+            }
+
+            match runtime_context.pointer() {
+                Some(pointer) => pointer,
+                _ => return Ok(())
+            }
+        };
+
+        for (entered_container, path) in entered_containers {
+            self.visit_container(&entered_container, &path, true);
+        }
+
+        let has_moved = match container.get(index) {
+            Some(runtime_object) => self.perform(runtime_object)?,
+            _ => false
+        };
+
+        if !has_moved {
+            self.next_content();
+        }
+
+        // Start a thread after moving the content pointer, so that the current thread resumes
+        // after the thread instruction once the new thread is done
+        if let Some(&RuntimeObject::ControlCommand(ControlCommand::StartThread)) = container.get(index) {
+            self.state.callstack().push_thread();
+        }
+
+        Ok(())
+    }
+
+    /// Perform the runtime object, returns true if the content pointer has been moved as part of
+    /// the evaluation (for instance by a divert).
+    fn perform(&mut self, runtime_object: &RuntimeObject) -> Result<bool, InkError> {
+        match runtime_object {
+            // An empty container, just step over it
+            &RuntimeObject::Container(_) => {},
+            // A variable pointer with an unknown context is resolved to the context of the variable
+            &RuntimeObject::Value(Value::VariablePointer(ref name, -1)) if self.state.in_expression_evaluation() => {
+                let context_index = self.state.context_for_variable_named(name);
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::VariablePointer(name.clone(), context_index)));
+            },
+            &RuntimeObject::Value(ref value) => {
+                if self.state.in_expression_evaluation() {
+                    self.state.push_evaluation_stack(runtime_object.clone());
+                } else {
+                    self.state.push_to_output_stream(RuntimeObject::Value(Value::String(value.to_string())));
+                }
+            },
+            &RuntimeObject::Void => {
+                if self.state.in_expression_evaluation() {
+                    self.state.push_evaluation_stack(RuntimeObject::Void);
+                }
+            },
+            &RuntimeObject::Glue(_) => self.state.push_to_output_stream(runtime_object.clone()),
+            &RuntimeObject::Tag(_) => {
+                if self.state.in_expression_evaluation() {
+                    self.state.push_evaluation_stack(runtime_object.clone());
+                } else {
+                    self.state.push_to_output_stream(runtime_object.clone());
+                }
+            },
+            &RuntimeObject::Divert(ref divert) => return self.perform_divert(divert),
+            &RuntimeObject::Choice(ref choice_point) => {
+                if let Some(choice) = self.process_choice(choice_point)? {
+                    self.state.add_choice(choice);
+                }
+            },
+            &RuntimeObject::VariableAssignment(ref variable_assignment) => {
+                let value = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(value) => value,
+                    runtime_object => return Err(InkError::from_message(format!("Cannot assign {} to variable {}", runtime_object, variable_assignment.name())))
+                };
+
+                self.state.assign(variable_assignment, value)?;
+            },
+            &RuntimeObject::VariableReference(ref variable_reference) => {
+                // An undeclared variable may be a list item, otherwise it defaults to 0
+                let value = self.state.variable(variable_reference.name())
+                    .or_else(|| self.runtime_graph.list_definitions().find_single_item_list_with_name(variable_reference.name()).map(Value::List))
+                    .unwrap_or(Value::Int(0));
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(value));
+            },
+            &RuntimeObject::ReadCount(ref read_count) => {
+                let target = self.absolute_path(read_count.target())?;
+                let count = match self.runtime_graph.resolve_container_path(&target) {
+                    Some((container, path)) => visit_count_for_container(&self.state, container, &path)?,
+                    _ => return Err(InkError::from_message(format!("Failed to find container for read count at path {}", read_count.target())))
+                };
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(count)));
+            },
+            &RuntimeObject::NativeFunctionCall(ref native_function_call) => self.perform_native_function_call(native_function_call)?,
+            &RuntimeObject::ControlCommand(ref control_command) => return self.perform_control_command(control_command),
+            _ => return Err(InkError::from_message(format!("Unsupported runtime object: {}", runtime_object)))
+        }
+
+        Ok(false)
+    }
+
+    /// Generate a choice from a choice point, popping its condition and text from the evaluation
+    /// stack. Returns None if the choice should not be shown.
+    fn process_choice(&mut self, choice_point: &ChoicePoint) -> Result<Option<Choice>, InkError> {
+        let mut show_choice = true;
+
+        if choice_point.has_condition() {
+            show_choice = match self.pop_evaluation_stack()? {
+                RuntimeObject::Value(ref value) => value.is_truthy().ok_or_else(|| {
+                    InkError::from_message(format!("Cannot use {} as a choice condition", value))
+                })?,
+                runtime_object => return Err(InkError::from_message(format!("Cannot use {} as a choice condition", runtime_object)))
+            };
+        }
+
+        let mut tags = Vec::new();
+        let choice_only_text = if choice_point.has_choice_only_content() { self.pop_choice_text(&mut tags)? } else { String::new() };
+        let start_text = if choice_point.has_start_content() { self.pop_choice_text(&mut tags)? } else { String::new() };
+
+        // The target of the choice is relative to the choice point, the choice needs the
+        // absolute path as it is made from elsewhere
+        let mut choice_point = choice_point.clone();
+        if let Some(path) = choice_point.path_on_choice().cloned() {
+            choice_point.set_path_on_choice(self.absolute_path(&path)?);
+        }
+
+        if choice_point.once_only() {
+            if let Some(path) = choice_point.path_on_choice() {
+                let visit_count = match self.runtime_graph.resolve_container_path(path) {
+                    Some((container, path)) => visit_count_for_container(&self.state, container, &path)?,
+                    _ => return Err(InkError::from_message(format!("Failed to find choice target at path {}", path)))
+                };
+
+                if visit_count > 0 {
+                    show_choice = false;
+                }
+            }
+        }
+
+        if !show_choice {
+            return Ok(None);
+        }
+
+        // The choice point is under the content pointer
+        let source_path = match self.state.callstack().runtime_context().and_then(|runtime_context| runtime_context.ancestry().pop()) {
+            Some((_, index, path)) => Path::from_fragments(path.iter().cloned().chain(Some(Fragment::Index(index))).collect(), false),
+            _ => return Err(InkError::from_message("Cannot generate a choice without a content pointer"))
+        };
+
+        let thread = match self.state.callstack().fork_thread() {
+            Some(thread) => thread,
+            _ => return Err(InkError::from_message("Cannot generate a choice without a thread"))
+        };
+
+        let mut choice = Choice::from_choice_point(choice_point, thread);
+        choice.set_source_path(source_path);
+        choice.set_text(format!("{}{}", start_text, choice_only_text).trim_matches(|c| c == ' ' || c == '\t').to_owned());
+        choice.set_tags(tags);
+
+        Ok(Some(choice))
+    }
+
+    /// Pop a choice text from the evaluation stack, followed by the tags evaluated along with it
+    /// which are inserted in order at the start of the given tags.
+    fn pop_choice_text(&mut self, tags: &mut Vec<String>) -> Result<String, InkError> {
+        let text = match self.pop_evaluation_stack()? {
+            RuntimeObject::Value(Value::String(text)) => text,
+            runtime_object => return Err(InkError::from_message(format!("Expected choice text, got {}", runtime_object)))
+        };
+
+        while let Some(&RuntimeObject::Tag(_)) = self.state.peek_evaluation_stack() {
+            if let Some(RuntimeObject::Tag(tag)) = self.state.pop_evaluation_stack() {
+                tags.insert(0, tag.text().clone());
+            }
+        }
+
+        Ok(text)
+    }
+
+    fn perform_divert(&mut self, divert: &Divert) -> Result<bool, InkError> {
+        // A false condition cancels the divert
+        if divert.is_conditional() {
+            let condition = match self.pop_evaluation_stack()? {
+                RuntimeObject::Value(Value::DivertTarget(ref path)) => {
+                    return Err(InkError::from_message(format!("Shouldn't use a divert target (to {}) as a conditional value. Did you intend a function call 'likeThis()' or a read count check 'likeThis'? (no arrows)", path)));
+                },
+                RuntimeObject::Value(ref value) => value.is_truthy().ok_or_else(|| {
+                    InkError::from_message(format!("Cannot use {} as a divert condition", value))
+                })?,
+                runtime_object => return Err(InkError::from_message(format!("Cannot use {} as a divert condition", runtime_object)))
+            };
+
+            if !condition {
+                return Ok(false);
+            }
+        }
+
+        if divert.is_external() {
+            return match divert.target() {
+                Some(&TargetType::Path(ref path)) => self.call_external_function(path, divert.external_args().unwrap_or(0) as usize),
+                _ => Err(InkError::from_message("Unsupported divert type"))
+            };
+        }
+
+        let target = match divert.target() {
+            Some(&TargetType::Path(ref path)) => path.clone(),
+            Some(&TargetType::Name(ref name)) => self.variable_divert_target(name)?,
+            None => return Err(InkError::from_message("Divert has no target"))
+        };
+
+        // Tunnels and functions return to the divert once they are done
+        if divert.pushes_to_stack() {
+            let output_stream_length = self.state.output_stream().len();
+            self.state.callstack().push(*divert.stack_push_type(), output_stream_length)?;
+        }
+
+        self.divert_to_path(&target)?;
+        Ok(true)
+    }
+
+    /// Call the game function bound to an EXTERNAL function with arguments popped from the
+    /// evaluation stack, or the ink function of the same name if fallbacks are allowed. Returns
+    /// true if the content pointer has been moved to the ink function.
+    fn call_external_function(&mut self, path: &Path, argument_count: usize) -> Result<bool, InkError> {
+        let name = path.to_string();
+        let lookahead_safe = self.external_functions.get(&name).map(|external_function| external_function.lookahead_safe());
+
+        match lookahead_safe {
+            // A newline has been output, the line ends before the function instead
+            Some(false) if self.state_snapshot_at_last_newline.is_some() => {
+                self.saw_lookahead_unsafe_function_after_newline = true;
+                return Ok(false);
+            },
+            Some(_) => {},
+            None if self.allow_external_function_fallbacks => {
+                if self.runtime_graph.resolve_container_path(path).is_none() {
+                    return Err(InkError::from_message(format!("Trying to call EXTERNAL function '{}' which has not been bound, and fallback ink function could not be found.", name)));
+                }
+
+                let output_stream_length = self.state.output_stream().len();
+                self.state.callstack().push(PushPopType::Function, output_stream_length)?;
+                self.divert_to_path(path)?;
+                return Ok(true);
+            },
+            None => return Err(InkError::from_message(format!("Trying to call EXTERNAL function '{}' which has not been bound (and ink fallbacks disabled).", name)))
+        }
+
+        let value_count = self.state.evaluation_stack().iter().rev()
+            .take_while(|runtime_object| runtime_object.as_value().is_some())
+            .count();
+
+        if value_count < argument_count {
+            return Err(InkError::from_message(format!("External function '{}' expects {} arguments, but only {} were given", name, argument_count, value_count)));
+        }
+
+        // The arguments are on the stack in reverse order
+        let mut arguments = Vec::with_capacity(argument_count);
+        for _ in 0..argument_count {
+            if let Some(RuntimeObject::Value(value)) = self.state.pop_evaluation_stack() {
+                arguments.push(value);
+            }
+        }
+
+        arguments.reverse();
+
+        let result = self.external_functions.get_mut(&name).and_then(|external_function| external_function.call(&arguments));
+        self.state.push_evaluation_stack(result.map_or(RuntimeObject::Void, RuntimeObject::Value));
+
+        Ok(false)
+    }
+
+    /// Get the target of a divert to a variable, e.g. `-> target_var`.
+    fn variable_divert_target(&self, name: &str) -> Result<Path, InkError> {
+        match self.state.variable(name) {
+            Some(Value::DivertTarget(path)) => Ok(path),
+            Some(Value::Int(0)) => Err(InkError::from_message(format!("Tried to divert to a target from a variable, but the variable ({}) didn't contain a divert target, it was empty/null (the value 0).", name))),
+            Some(value) => Err(InkError::from_message(format!("Tried to divert to a target from a variable, but the variable ({}) didn't contain a divert target, it contained '{}'.", name, value))),
+            None => Err(InkError::from_message(format!("Tried to divert using a target from a variable that could not be found ({})", name)))
+        }
     }
 
-}
\ No newline at end of file
+    fn perform_control_command(&mut self, control_command: &ControlCommand) -> Result<bool, InkError> {
+        match *control_command {
+            ControlCommand::EvalStart => {
+                if self.state.in_expression_evaluation() {
+                    return Err(InkError::from_message("Already in expression evaluation"));
+                }
+
+                self.state.set_in_expression_evaluation(true);
+            },
+            ControlCommand::EvalEnd => {
+                if !self.state.in_expression_evaluation() {
+                    return Err(InkError::from_message("Not in expression evaluation mode"));
+                }
+
+                self.state.set_in_expression_evaluation(false);
+            },
+            ControlCommand::EvalOutput => {
+                match self.pop_evaluation_stack()? {
+                    RuntimeObject::Void => {},
+                    RuntimeObject::Value(value) => self.state.push_to_output_stream(RuntimeObject::Value(Value::String(value.to_string()))),
+                    runtime_object => return Err(InkError::from_message(format!("Cannot output {}", runtime_object)))
+                }
+            },
+            ControlCommand::Duplicate => {
+                let runtime_object = match self.state.peek_evaluation_stack() {
+                    Some(runtime_object) => runtime_object.clone(),
+                    _ => return Err(InkError::from_message("Evaluation stack is empty"))
+                };
+
+                self.state.push_evaluation_stack(runtime_object);
+            },
+            ControlCommand::PopEvaluatedValue => {
+                self.pop_evaluation_stack()?;
+            },
+            ControlCommand::PopFunction | ControlCommand::PopTunnel => {
+                let stack_push_type = match *control_command {
+                    ControlCommand::PopFunction => PushPopType::Function,
+                    _ => PushPopType::Tunnel
+                };
+
+                // A tunnel onwards may divert to a new target after returning: ->-> target
+                let override_tunnel_return_target = match stack_push_type {
+                    PushPopType::Tunnel => match self.pop_evaluation_stack()? {
+                        RuntimeObject::Value(Value::DivertTarget(target)) => Some(target),
+                        RuntimeObject::Void => None,
+                        runtime_object => return Err(InkError::from_message(format!("Expected void or a divert target for the tunnel onwards, got {}", runtime_object)))
+                    },
+                    _ => None
+                };
+
+                // Returning from a function called by the game ends its evaluation
+                if self.in_function_evaluation_from_game() {
+                    if let Some(runtime_context) = self.state.callstack().runtime_context_mut() {
+                        runtime_context.clear();
+                    }
+
+                    return Ok(true);
+                }
+
+                if !self.state.callstack().can_pop(stack_push_type) {
+                    let expected = match self.state.callstack().current_stack_push_type() {
+                        _ if self.state.callstack().depth() <= 1 => "end of flow (-> END or choice)",
+                        Some(PushPopType::Function) => "function return statement (~ return)",
+                        _ => "tunnel onwards statement (->->)"
+                    };
+
+                    let found = match stack_push_type {
+                        PushPopType::Function => "function return statement (~ return)",
+                        _ => "tunnel onwards statement (->->)"
+                    };
+
+                    return Err(InkError::from_message(format!("Found {}, when expected {}", found, expected)));
+                }
+
+                self.state.pop_callstack();
+
+                if let Some(target) = override_tunnel_return_target {
+                    self.divert_to_path(&target)?;
+                    return Ok(true);
+                }
+            },
+            ControlCommand::BeginString => {
+                if !self.state.in_expression_evaluation() {
+                    return Err(InkError::from_message("Expected to be in an expression when evaluating a string"));
+                }
+
+                self.state.set_in_expression_evaluation(false);
+                self.state.push_to_output_stream(RuntimeObject::ControlCommand(ControlCommand::BeginString));
+            },
+            ControlCommand::EndString => {
+                // Collapse all the content output since the BeginString marker into a single string,
+                // the tags output in the meantime are kept in the output stream
+                let mut count = 0;
+                let mut content: Vec<&str> = Vec::new();
+                let mut tags = Vec::new();
+
+                for runtime_object in self.state.output_stream().iter().rev() {
+                    count += 1;
+
+                    match runtime_object {
+                        &RuntimeObject::ControlCommand(ControlCommand::BeginString) => break,
+                        &RuntimeObject::Value(Value::String(ref text)) => content.push(text),
+                        &RuntimeObject::Tag(_) => tags.push(runtime_object.clone()),
+                        _ => {}
+                    }
+                }
+
+                content.reverse();
+                let text = content.concat();
+
+                self.state.pop_from_output_stream(count);
+
+                for tag in tags.into_iter().rev() {
+                    self.state.push_to_output_stream(tag);
+                }
+
+                self.state.set_in_expression_evaluation(true);
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::String(text)));
+            },
+            ControlCommand::BeginTag => self.state.push_to_output_stream(RuntimeObject::ControlCommand(ControlCommand::BeginTag)),
+            ControlCommand::EndTag => {
+                if self.state.in_string_evaluation() {
+                    // Collapse the tag content into a tag, picked up by the next choice point
+                    let mut count = 0;
+                    let mut content: Vec<&str> = Vec::new();
+
+                    for runtime_object in self.state.output_stream().iter().rev() {
+                        count += 1;
+
+                        match runtime_object {
+                            &RuntimeObject::ControlCommand(ControlCommand::BeginTag) => break,
+                            &RuntimeObject::ControlCommand(_) => return Err(InkError::from_message("Unexpected control command while extracting tag from choice")),
+                            &RuntimeObject::Value(Value::String(ref text)) => content.push(text),
+                            _ => {}
+                        }
+                    }
+
+                    content.reverse();
+                    let text = clean_output_whitespace(&content.concat());
+
+                    self.state.pop_from_output_stream(count);
+                    self.state.push_evaluation_stack(RuntimeObject::Tag(Tag::new(text)));
+                } else {
+                    self.state.push_to_output_stream(RuntimeObject::ControlCommand(ControlCommand::EndTag));
+                }
+            },
+            ControlCommand::NoOp => {},
+            ControlCommand::ChoiceCount => {
+                let choice_count = self.state.choices().len() as i32;
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(choice_count)));
+            },
+            ControlCommand::TurnsSince | ControlCommand::ReadCount => {
+                let target = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::DivertTarget(target)) => target,
+                    runtime_object => return Err(InkError::from_message(format!("{} expected a divert target (knot, stitch, label name), but saw {}", control_command, runtime_object)))
+                };
+
+                // An unknown target defaults to never visited
+                let count = match self.runtime_graph.resolve_container_path(&target) {
+                    Some((container, path)) => match *control_command {
+                        ControlCommand::TurnsSince => turns_since_for_container(&self.state, container, &path)?,
+                        _ => visit_count_for_container(&self.state, container, &path)?
+                    },
+                    _ => match *control_command {
+                        ControlCommand::TurnsSince => -1,
+                        _ => 0
+                    }
+                };
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(count)));
+            },
+            ControlCommand::VisitIndex => {
+                let (container, path) = match self.state.callstack().runtime_context() {
+                    Some(runtime_context) => match (runtime_context.get_container(), runtime_context.container_path()) {
+                        (Some(container), Some(path)) => (container.clone(), path),
+                        _ => return Err(InkError::from_message("No current container for the visit index"))
+                    },
+                    _ => return Err(InkError::from_message("No current container for the visit index"))
+                };
+
+                // The current container has already been counted when entered, get an index
+                let visit_index = visit_count_for_container(&self.state, &container, &path)? - 1;
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(visit_index)));
+            },
+            ControlCommand::Random => {
+                let max = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::Int(max)) => max,
+                    _ => return Err(InkError::from_message("Invalid value for maximum parameter of RANDOM(min, max)"))
+                };
+
+                let min = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::Int(min)) => min,
+                    _ => return Err(InkError::from_message("Invalid value for minimum parameter of RANDOM(min, max)"))
+                };
+
+                // +1 because the range includes both min and max, e.g. RANDOM(1, 6) for a dice roll
+                let random_range = match max.checked_sub(min).and_then(|range| range.checked_add(1)) {
+                    Some(random_range) => random_range,
+                    _ => return Err(InkError::from_message("RANDOM was called with a range that exceeds the size that ink numbers can use"))
+                };
+
+                if random_range <= 0 {
+                    return Err(InkError::from_message(format!("RANDOM was called with minimum as {} and maximum as {}. The maximum must be larger", min, max)));
+                }
+
+                let chosen_value = self.state.next_random() % random_range + min;
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(chosen_value)));
+            },
+            ControlCommand::SeedRandom => {
+                let seed = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::Int(seed)) => seed,
+                    _ => return Err(InkError::from_message("Invalid value passed to SEED_RANDOM"))
+                };
+
+                // The story seed affects both RANDOM and shuffle sequences
+                self.state.set_story_seed(seed);
+                self.state.push_evaluation_stack(RuntimeObject::Void);
+            },
+            ControlCommand::SequenceShuffleIndex => {
+                let shuffle_index = self.next_sequence_shuffle_index()?;
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::Int(shuffle_index)));
+            },
+            // Handled once the content pointer has moved, see step
+            ControlCommand::StartThread => {},
+            ControlCommand::Done => {
+                // Return to the thread which started the current thread, past the divert which
+                // started it, otherwise stop the flow
+                if self.state.callstack().can_pop_thread() {
+                    self.state.callstack().pop_thread();
+                    return Ok(false);
+                }
+
+                if let Some(runtime_context) = self.state.callstack().runtime_context_mut() {
+                    runtime_context.clear();
+                }
+
+                self.state.set_did_safe_exit(true);
+                return Ok(true);
+            },
+            ControlCommand::End => {
+                self.state.callstack().force_end();
+                self.state.set_did_safe_exit(true);
+                return Ok(true);
+            },
+            ControlCommand::ListFromInt => {
+                let value = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::Int(value)) => value,
+                    _ => return Err(InkError::from_message("Passed non-integer when creating a list element from a numerical value."))
+                };
+
+                let list_name = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::String(list_name)) => list_name,
+                    _ => return Err(InkError::from_message("Expected a list name when creating a list element from a numerical value."))
+                };
+
+                let ink_list = match self.runtime_graph.list_definitions().list_definition(&list_name) {
+                    Some(list_definition) => match list_definition.item_with_value(value) {
+                        Some(item) => InkList::from_item(item, value),
+                        _ => InkList::new()
+                    },
+                    _ => return Err(InkError::from_message(format!("Failed to find LIST called {}", list_name)))
+                };
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::List(ink_list)));
+            },
+            ControlCommand::ListRange => {
+                let max = self.pop_evaluation_stack()?;
+                let min = self.pop_evaluation_stack()?;
+                let target_list = self.pop_evaluation_stack()?;
+
+                let range = match (target_list, min, max) {
+                    (RuntimeObject::Value(Value::List(ref target_list)), RuntimeObject::Value(ref min), RuntimeObject::Value(ref max)) => {
+                        let min_value = match min {
+                            &Value::Int(value) => Some(value),
+                            &Value::List(ref list) => Some(list.min_item().map_or(0, |(_, value)| value)),
+                            _ => None
+                        };
+
+                        let max_value = match max {
+                            &Value::Int(value) => Some(value),
+                            &Value::List(ref list) => Some(list.max_item().map_or(i32::MAX, |(_, value)| value)),
+                            _ => None
+                        };
+
+                        match (min_value, max_value) {
+                            (Some(min_value), Some(max_value)) => target_list.range(min_value, max_value),
+                            _ => return Err(InkError::from_message("Expected list, minimum and maximum for LIST_RANGE"))
+                        }
+                    },
+                    _ => return Err(InkError::from_message("Expected list, minimum and maximum for LIST_RANGE"))
+                };
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::List(range)));
+            },
+            ControlCommand::ListRandom => {
+                let list = match self.pop_evaluation_stack()? {
+                    RuntimeObject::Value(Value::List(list)) => list,
+                    _ => return Err(InkError::from_message("Expected list for LIST_RANDOM"))
+                };
+
+                let random_list = if list.is_empty() {
+                    InkList::new()
+                } else {
+                    // The item is picked in insertion order like the reference runtime. Lists read
+                    // from a saved state still differ: their items come sorted by name
+                    let random_item_index = self.state.next_random() as usize % list.len();
+                    let (item, value) = list.items()[random_item_index];
+
+                    // The origin of the new list is simply the origin of its item
+                    let mut random_list = InkList::from_item(item.clone(), value);
+                    random_list.add_origin_names(item.origin_name().cloned().into_iter().collect());
+                    random_list
+                };
+
+                self.state.push_evaluation_stack(RuntimeObject::Value(Value::List(random_list)));
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Pop the number of elements of a shuffle sequence and the number of times it has been
+    /// visited, and get the index of the element to show. The shuffle is derived from the path
+    /// of the sequence container, the number of times the whole sequence has been gone through
+    /// and the story seed, so that it is the same each time the sequence is reached.
+    fn next_sequence_shuffle_index(&mut self) -> Result<i32, InkError> {
+        let element_count = match self.pop_evaluation_stack()? {
+            RuntimeObject::Value(Value::Int(element_count)) if element_count > 0 => element_count,
+            _ => return Err(InkError::from_message("Expected number of elements in sequence for shuffle index"))
+        };
+
+        let sequence_count = match self.pop_evaluation_stack()? {
+            RuntimeObject::Value(Value::Int(sequence_count)) => sequence_count,
+            _ => return Err(InkError::from_message("Expected sequence count for shuffle index"))
+        };
+
+        let sequence_path = match self.state.callstack().runtime_context().and_then(|runtime_context| runtime_context.container_path()) {
+            Some(path) => path.to_string(),
+            _ => return Err(InkError::from_message("No current container for the shuffle sequence"))
+        };
+
+        let loop_index = sequence_count / element_count;
+        let iteration_index = sequence_count % element_count;
+
+        let sequence_hash = sequence_path.chars().fold(0i32, |hash, c| hash.wrapping_add(c as i32));
+        let random_seed = sequence_hash.wrapping_add(loop_index).wrapping_add(self.state.story_seed());
+        let mut random = Prng::new(random_seed);
+
+        let mut unpicked_indices: Vec<i32> = (0..element_count).collect();
+
+        for _ in 0..iteration_index {
+            let chosen = random.next() as usize % unpicked_indices.len();
+            unpicked_indices.remove(chosen);
+        }
+
+        let chosen = random.next() as usize % unpicked_indices.len();
+        Ok(unpicked_indices[chosen])
+    }
+
+    fn perform_native_function_call(&mut self, native_function_call: &NativeFunctionCall) -> Result<(), InkError> {
+        let mut parameters = Vec::with_capacity(native_function_call.number_of_parameters());
+
+        for _ in 0..native_function_call.number_of_parameters() {
+            match self.pop_evaluation_stack()? {
+                RuntimeObject::Value(value) => parameters.push(value),
+                RuntimeObject::Void => return Err(InkError::from_message(format!("Attempting to perform {} on a void value. Did you forget to 'return' a value from a function you called here?", native_function_call))),
+                runtime_object => return Err(InkError::from_message(format!("Cannot perform {} on {}", native_function_call, runtime_object)))
+            }
+        }
+
+        parameters.reverse();
+
+        let result = native_function_call.call(parameters, self.runtime_graph.list_definitions())?;
+        self.state.push_evaluation_stack(RuntimeObject::Value(result));
+
+        Ok(())
+    }
+
+    fn pop_evaluation_stack(&mut self) -> Result<RuntimeObject, InkError> {
+        match self.state.pop_evaluation_stack() {
+            Some(runtime_object) => Ok(runtime_object),
+            _ => Err(InkError::from_message("Evaluation stack is empty"))
+        }
+    }
+
+    /// Get the absolute path of a path relative to the content under the content pointer.
+    fn absolute_path(&mut self, path: &Path) -> Result<Path, InkError> {
+        if !path.is_relative() {
+            return Ok(path.clone());
+        }
+
+        let container_path = match self.state.callstack().runtime_context() {
+            Some(runtime_context) => runtime_context.container_path(),
+            _ => None
+        };
+
+        match container_path.and_then(|container_path| path.to_absolute(&container_path)) {
+            Some(absolute_path) => Ok(absolute_path),
+            _ => Err(InkError::from_message(format!("Failed to resolve relative path: {}", path)))
+        }
+    }
+
+    fn divert_to_path(&mut self, path: &Path) -> Result<(), InkError> {
+        let path = &self.absolute_path(path)?;
+        let root_container = self.runtime_graph.root_container();
+        let (has_moved, previous_containers) = match self.state.callstack().runtime_context_mut() {
+            Some(runtime_context) => {
+                let mut previous_containers = runtime_context.containers();
+
+                if let Some(&RuntimeObject::Container(ref container)) = runtime_context.get() {
+                    previous_containers.push(container.clone());
+                }
+
+                (runtime_context.move_to_path(root_container, path), previous_containers)
+            },
+            _ => (false, Vec::new())
+        };
+
+        if !has_moved {
+            return Err(InkError::from_message(format!("Failed to find content at path: {}", path)));
+        }
+
+        self.visit_changed_containers_due_to_divert(&previous_containers);
+        Ok(())
+    }
+
+    /// Mark the containers newly entered by a divert as visited. The containers that were
+    /// already open before the divert are not visited again, unless they are only counted when
+    /// entered at the start.
+    fn visit_changed_containers_due_to_divert(&mut self, previous_containers: &[Rc<Container>]) {
+        let ancestry = match self.state.callstack().runtime_context() {
+            Some(runtime_context) => runtime_context.ancestry(),
+            _ => return
+        };
+
+        let mut all_children_entered_at_start = true;
+
+        for (container, index, path) in ancestry.into_iter().rev() {
+            let was_open = previous_containers.iter().any(|previous_container| Rc::ptr_eq(previous_container, &container));
+
+            if was_open && !container.count_at_start_only() {
+                break;
+            }
+
+            let entering_at_start = index == 0 && !container.is_empty() && all_children_entered_at_start;
+            all_children_entered_at_start = entering_at_start;
+
+            self.visit_container(&container, &path, entering_at_start);
+        }
+    }
+
+    /// Record a visit of a container, either entered at its start or in the middle of its content.
+    fn visit_container(&mut self, container: &Container, path: &Path, at_start: bool) {
+        if container.count_at_start_only() && !at_start {
+            return;
+        }
+
+        if container.visits_should_be_counted() {
+            self.state.increment_visit_count(&path.to_string());
+        }
+
+        if container.turn_index_should_be_counted() {
+            self.state.record_turn_index_visit(&path.to_string());
+        }
+    }
+
+    /// Move the content pointer to the next runtime object.
+    fn next_content(&mut self) {
+        let has_next = match self.state.callstack().runtime_context_mut() {
+            Some(runtime_context) => runtime_context.increment(),
+            _ => false
+        };
+
+        if has_next {
+            return;
+        }
+
+        // Out of content at the end of a function which does not return anything
+        if self.state.callstack().can_pop(PushPopType::Function) {
+            self.state.pop_callstack();
+
+            // The caller may expect a returned value
+            if self.state.in_expression_evaluation() {
+                self.state.push_evaluation_stack(RuntimeObject::Void);
+            }
+
+            // Step past the point where the function was called
+            self.next_content();
+        } else if self.state.callstack().pop_thread() {
+            // Step past the divert which started the thread
+            self.next_content();
+        }
+    }
+}
+
+/// Get the number of visits of a counted container.
+fn visit_count_for_container(state: &StoryState, container: &Container, path: &Path) -> Result<i32, InkError> {
+    if !container.visits_should_be_counted() {
+        return Err(InkError::from_message(format!("Read count for target {} unknown, the story may need to be compiled with count all visits enabled", path)));
+    }
+
+    Ok(state.visit_count_at_path(&path.to_string()))
+}
+
+/// Get the number of turns since a counted container was last visited, -1 if it never was.
+fn turns_since_for_container(state: &StoryState, container: &Container, path: &Path) -> Result<i32, InkError> {
+    if !container.turn_index_should_be_counted() {
+        return Err(InkError::from_message(format!("TURNS_SINCE() for target {} unknown", path)));
+    }
+
+    Ok(state.turns_since_at_path(&path.to_string()))
+}
+
+/// Get the tags at the start of a container, before any other content. Nested containers at the
+/// start of the container are entered.
+fn tags_at_start_of_container(container: &Rc<Container>) -> Vec<String> {
+    let mut container = container.clone();
+
+    while let Some(first_container) = first_container(&container) {
+        container = first_container;
+    }
+
+    let mut tags = Vec::new();
+    let mut in_tag = false;
+
+    // Since ink v21, static tags are written as text between BeginTag and EndTag commands
+    for i in 0..container.len() {
+        match container.get(i) {
+            Some(&RuntimeObject::Tag(ref tag)) => tags.push(tag.text().clone()),
+            Some(&RuntimeObject::ControlCommand(ControlCommand::BeginTag)) => in_tag = true,
+            Some(&RuntimeObject::ControlCommand(ControlCommand::EndTag)) => in_tag = false,
+            Some(&RuntimeObject::Value(Value::String(ref text))) if in_tag => tags.push(text.clone()),
+            _ => break
+        }
+    }
+
+    tags
+}
+
+fn first_container(container: &Container) -> Option<Rc<Container>> {
+    match container.get(0) {
+        Some(&RuntimeObject::Container(ref first_container)) => Some(first_container.clone()),
+        _ => None
+    }
+}
+
+/// How the output changed since a newline was output.
+enum OutputStateChange {
+    NoChange,
+    ExtendedBeyondNewline,
+    NewlineRemoved
+}
+
+fn output_state_change(previous_text: &str, current_text: &str, previous_tag_count: usize, current_tag_count: usize) -> OutputStateChange {
+    let newline_still_exists = current_text.len() >= previous_text.len() && !previous_text.is_empty()
+        && current_text.as_bytes()[previous_text.len() - 1] == b'\n';
+
+    if !newline_still_exists {
+        return OutputStateChange::NewlineRemoved;
+    }
+
+    // A new tag starts a new line
+    if current_tag_count > previous_tag_count {
+        return OutputStateChange::ExtendedBeyondNewline;
+    }
+
+    // New content which is not just inline whitespace, which could still be removed by glue
+    if current_text[previous_text.len()..].chars().any(|c| c != ' ' && c != '\t') {
+        return OutputStateChange::ExtendedBeyondNewline;
+    }
+
+    OutputStateChange::NoChange
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::ink_list::{InkList, InkListItem};
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn hello_world_test() {
+        let json = r###"{"inkVersion":17,"root":[["^Hello, world!","\n",["done",{"#n":"g-0"}],null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello, world!\n"));
+        assert_eq!(story.current_text().unwrap(), "Hello, world!\n");
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+        assert!(story.current_text().is_none());
+    }
+
+    #[test]
+    fn divert_to_knot_test() {
+        let json = r###"{"inkVersion":17,"root":[["^Line one","\n","\n","^Line two","\n",{"->":"knot"},["done",{"#n":"g-0"}],null],"done",{"knot":["^In the knot.","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line one\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line two\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In the knot.\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn named_content_is_not_stepped_into_test() {
+        // The flow stops in the knot, the content after the root's first container is not reached
+        let json = r###"{"inkVersion":17,"root":[[{"->":"knot"},null],"^Never printed","\n",{"knot":["^Last line","done",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Last line"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn out_of_content_test() {
+        // Hello, without any -> DONE or -> END
+        let json = r###"{"inkVersion":21,"root":[["^Hello","\n",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "ran out of content. Do you need a '-> DONE' or '-> END'?");
+
+        // -> tunnel ->
+        // After
+        // == tunnel ==
+        // In tunnel
+        let json = r###"{"inkVersion":21,"root":[[{"->t->":"tunnel"},"^After","\n","end",null],"done",{"tunnel":["^In tunnel","\n",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "unexpectedly reached end of content. Do you need a '->->' to return from a tunnel?");
+
+        // A function element left without content, running out of a function usually returns from it
+        let json = r###"{"inkVersion":21,"root":[["done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        story.state.callstack().push(PushPopType::Function, 0).unwrap();
+        story.state.callstack().runtime_context_mut().unwrap().clear();
+
+        let error = story.check_end_of_content().unwrap_err();
+        assert_eq!(error.to_string(), "unexpectedly reached end of content. Do you need a '~ return'?");
+    }
+
+    #[test]
+    fn evaluation_output_test() {
+        let json = r###"{"inkVersion":17,"root":[["^Value: ","ev",42,"out",1.5,"out","/ev","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Value: 421.5\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn duplicate_and_pop_test() {
+        let json = r###"{"inkVersion":17,"root":[["ev",1,"du","out",2,"pop","out","choiceCnt","out","/ev","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("110\n"));
+    }
+
+    #[test]
+    fn string_evaluation_test() {
+        let json = r###"{"inkVersion":17,"root":[["^A ","ev","str","^Hello ","ev",2,"out","/ev","^ worlds","/str","du","out","^, ","out","out","/ev","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("A Hello 2 worlds, Hello 2 worlds\n"));
+    }
+
+    #[test]
+    fn native_function_call_test() {
+        // {2 + 3 * 1.5} {"a" + 1} {7 / 2 == 3}
+        let json = r###"{"inkVersion":17,"root":[["ev",2,3,1.5,"*","+","out","/ev","^ ","ev","str","^a","/str",1,"+","out","/ev","^ ","ev",7,2,"/",3,"==","out","/ev","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("6.5 a1 true\n"));
+    }
+
+    #[test]
+    fn list_value_output_test() {
+        // LIST colours = red, green, blue
+        // {(red, blue)}
+        let json = r###"{"inkVersion":17,"root":[["ev",{"list":{"colours.blue":3,"colours.red":1}},"out","/ev","\n","done",null],null],"listDefs":{"colours":{"red":1,"green":2,"blue":3}}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("red, blue\n"));
+    }
+
+    #[test]
+    fn list_functions_test() {
+        // LIST colours = red, green, blue
+        // {LIST_INVERT(colours.red)} {colours(2)} {LIST_RANGE(LIST_ALL(red), 2, 3)} {(red, green) + 1}
+        let json = r###"{"inkVersion":17,"root":[["ev",{"list":{"colours.red":1}},"LIST_INVERT","out","/ev","^ ","ev","str","^colours","/str",2,"listInt","out","/ev","^ ","ev",{"list":{"colours.red":1}},"LIST_ALL",2,3,"range","out","/ev","^ ","ev",{"list":{"colours.red":1,"colours.green":2}},1,"+","out","/ev","\n","done",null],null],"listDefs":{"colours":{"red":1,"green":2,"blue":3}}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("green, blue green green, blue green, blue\n"));
+    }
+
+    #[test]
+    fn variables_test() {
+        // VAR gold = 5
+        // ~ gold = gold + 1
+        // ~ temp x = 2
+        // {gold} {x} {unknown}
+        let json = r###"{"inkVersion":17,"root":[["ev",{"VAR?":"gold"},1,"+","/ev",{"VAR=":"gold","re":true},"ev",2,"/ev",{"temp=":"x"},"ev",{"VAR?":"gold"},"out","/ev","^ ","ev",{"VAR?":"x"},"out","/ev","^ ","ev",{"VAR?":"unknown"},"out","/ev","\n","done",null],"done",{"global decl":["ev",5,{"VAR=":"gold"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("6 2 0\n"));
+        assert_eq!(story.state.variables_state().global_variable("gold"), Some(&Value::Int(6)));
+    }
+
+    #[test]
+    fn get_set_variable_test() {
+        // LIST colours = red, green, blue
+        // VAR gold = 5
+        // VAR weight = 1.5
+        // VAR colour = red
+        // {gold} {weight} {colour}
+        let json = r###"{"inkVersion":17,"root":[["ev",{"VAR?":"gold"},"out","/ev","^ ","ev",{"VAR?":"weight"},"out","/ev","^ ","ev",{"VAR?":"colour"},"out","/ev","\n","done",null],"done",{"global decl":["ev",5,{"VAR=":"gold"},1.5,{"VAR=":"weight"},{"list":{"colours.red":1}},{"VAR=":"colour"},"/ev","end",null]}],"listDefs":{"colours":{"red":1,"green":2,"blue":3}}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.variable("gold").unwrap(), Value::Int(5));
+        assert!(story.variable("silver").is_err());
+
+        story.set_variable("gold", Value::Int(10)).unwrap();
+        story.set_variable("weight", Value::Int(2)).unwrap();
+
+        let mut colour = InkList::new();
+        colour.insert(InkListItem::new("colours".to_owned(), "blue".to_owned()), 3);
+        story.set_variable("colour", Value::List(colour.clone())).unwrap();
+
+        assert_eq!(story.variable("weight").unwrap(), Value::Float(2.0));
+        assert_eq!(story.variable("colour").unwrap(), Value::List(colour));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("10 2 blue\n"));
+    }
+
+    #[test]
+    fn set_invalid_variable_test() {
+        let json = r###"{"inkVersion":17,"root":[["done",null],"done",{"global decl":["ev",5,{"VAR=":"gold"},{"list":{},"origins":["colours"]},{"VAR=":"colour"},"/ev","end",null]}],"listDefs":{"colours":{"red":1}}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        // Undeclared variable
+        assert!(story.set_variable("silver", Value::Int(1)).is_err());
+
+        // Incompatible types
+        assert!(story.set_variable("gold", Value::String("a lot".to_owned())).is_err());
+        assert!(story.set_variable("gold", Value::Float(1.5)).is_err());
+        assert!(story.set_variable("gold", Value::VariablePointer("colour".to_owned(), 0)).is_err());
+
+        // List items must come from the list definitions
+        let mut colour = InkList::new();
+        colour.insert(InkListItem::new("colours".to_owned(), "blue".to_owned()), 3);
+        assert!(story.set_variable("colour", Value::List(colour)).is_err());
+        assert_eq!(story.variable("gold").unwrap(), Value::Int(5));
+    }
+
+    #[test]
+    fn variable_observers_test() {
+        // VAR gold = 5
+        // VAR silver = 1
+        // ~ gold = 6
+        // ~ gold = 7
+        // ~ silver = 1
+        // Line one
+        // ~ silver = 2
+        // Line two
+        let json = r###"{"inkVersion":17,"root":[["ev",6,"/ev",{"VAR=":"gold","re":true},"ev",7,"/ev",{"VAR=":"gold","re":true},"ev",1,"/ev",{"VAR=":"silver","re":true},"^Line one","\n","ev",2,"/ev",{"VAR=":"silver","re":true},"^Line two","\n","done",null],"done",{"global decl":["ev",5,{"VAR=":"gold"},1,{"VAR=":"silver"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let gold_changes = Rc::new(RefCell::new(Vec::new()));
+        let all_changes = Rc::new(RefCell::new(Vec::new()));
+
+        let changes = gold_changes.clone();
+        let gold_handle = story.observe_variable("gold", move |_, value| changes.borrow_mut().push(value.clone()));
+        let changes = all_changes.clone();
+        story.observe_variables(move |name, value| changes.borrow_mut().push((name.to_owned(), value.clone())));
+
+        // The observers are only notified once, with the final value
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line one\n"));
+        assert_eq!(*gold_changes.borrow(), vec![Value::Int(7)]);
+        assert_eq!(*all_changes.borrow(), vec![("gold".to_owned(), Value::Int(7))]);
+
+        // Changes from the API are notified immediately
+        assert!(story.remove_variable_observer(gold_handle));
+        assert!(!story.remove_variable_observer(gold_handle));
+        story.set_variable("gold", Value::Int(1)).unwrap();
+        assert_eq!(all_changes.borrow().len(), 2);
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line two\n"));
+        assert_eq!(*gold_changes.borrow(), vec![Value::Int(7)]);
+        assert_eq!(*all_changes.borrow(), vec![("gold".to_owned(), Value::Int(7)),
+                                               ("gold".to_owned(), Value::Int(1)),
+                                               ("silver".to_owned(), Value::Int(2))]);
+    }
+
+    #[test]
+    fn choices_test() {
+        // Hello
+        // - (top)
+        // * [Choice A] You chose A -> top
+        // + Choice B[] then you chose B
+        //   -> END
+        // * {false} [Hidden]
+        let json = r###"{"inkVersion":17,"root":[["^Hello","\n",["ev","str","^Choice A","/str","/ev",{"*":"0.2.c-0","flg":20},"ev","str","^Choice B","/str","str","^ then","/str","/ev",{"*":"0.2.c-1","flg":6},"ev","str","^Hidden","/str",false,"/ev",{"*":"0.2.c-2","flg":5},"done",{"c-0":["^You chose A","\n",{"->":"0.2"},{"#f":5}],"c-1":["^You chose B","\n","end",null],"c-2":["end",null]}],null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.current_choices().is_none());
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        {
+            let choices = story.current_choices().unwrap();
+            assert_eq!(choices.len(), 2);
+            assert_eq!(choices[0].text(), Some("Choice A"));
+            assert_eq!(choices[1].text(), Some("Choice B then"));
+        }
+
+        assert!(story.make_choice(2).is_err());
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("You chose A\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        // Choice A is once-only and has been chosen
+        assert_eq!(story.current_choices().unwrap().len(), 1);
+        assert_eq!(story.current_choices().unwrap()[0].text(), Some("Choice B then"));
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("You chose B\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn invisible_default_choice_test() {
+        // - (top)
+        // * [Once] Chosen once -> top
+        // * -> fallback
+        // == fallback ==
+        // Fallback
+        let json = r###"{"inkVersion":17,"root":[[["ev","str","^Once","/str","/ev",{"*":"0.0.c-0","flg":20},{"*":"0.0.c-1","flg":24},"done",{"c-0":["^Chosen once","\n",{"->":"0.0"},{"#f":5}],"c-1":[{"->":"fallback"},{"#f":5}]}],null],"done",{"fallback":["^Fallback","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        // The fallback choice is hidden while there are other choices
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        assert_eq!(story.current_choices().unwrap().len(), 1);
+        assert!(story.followed_default_choice().is_none());
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Chosen once\n"));
+        assert!(story.followed_default_choice().is_none());
+
+        // Only the fallback choice remains, it is followed automatically
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Fallback\n"));
+        assert!(story.followed_default_choice().unwrap().is_invisible_default());
+        assert!(story.current_choices().is_none());
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn visit_counts_test() {
+        // -> knot
+        // == knot ==
+        // {knot} {TURNS_SINCE(-> knot)} {visit index} {READ_COUNT(-> knot.again)}
+        // + (again) [Again] -> knot
+        // + [Stop] -> END
+        let json = r###"{"inkVersion":21,"root":[[{"->":"knot"},null],"done",{"knot":["ev",{"CNT?":"knot"},"out","/ev","^ ","ev",{"^->":"knot"},"turns","out","/ev","^ ","ev","visit","out","/ev","^ ","ev",{"^->":"knot.c-0"},"readc","out","/ev","\n","ev","str","^Again","/str","/ev",{"*":"knot.c-0","flg":4},"ev","str","^Stop","/str","/ev",{"*":"knot.c-1","flg":4},"done",{"c-0":[{"->":"knot"},{"#f":5}],"c-1":["end",null],"#f":7}]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("1 0 0 0\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        assert_eq!(story.visit_count_at_path("knot").unwrap(), 1);
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("2 0 1 1\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        assert_eq!(story.visit_count_at_path("knot").unwrap(), 2);
+        assert_eq!(story.visit_count_at_path("knot.c-0").unwrap(), 1);
+        assert_eq!(story.visit_count_at_path("knot.c-1").unwrap(), 0);
+        assert!(story.visit_count_at_path("unknown").is_err());
+
+        story.make_choice(1).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+        assert_eq!(story.visit_count_at_path("knot").unwrap(), 2);
+    }
+
+    #[test]
+    fn turns_since_test() {
+        // -> knot
+        // == knot ==
+        // {TURNS_SINCE(-> other)}
+        // + [Again] -> knot
+        // + [Other] -> other
+        // == other ==
+        // -> knot
+        let json = r###"{"inkVersion":21,"root":[[{"->":"knot"},null],"done",{"knot":["ev",{"^->":"other"},"turns","out","/ev","\n","ev","str","^Again","/str","/ev",{"*":"knot.c-0","flg":4},"ev","str","^Other","/str","/ev",{"*":"knot.c-1","flg":4},"done",{"c-0":[{"->":"knot"},null],"c-1":[{"->":"other"},null],"#f":5}],"other":[{"->":"knot"},{"#f":7}]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("-1\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        story.make_choice(1).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("0\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("1\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("2\n"));
+    }
+
+    #[test]
+    fn uncounted_read_count_test() {
+        let json = r###"{"inkVersion":21,"root":[["ev",{"CNT?":"knot"},"out","/ev","done",null],"done",{"knot":["end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+
+    #[test]
+    fn random_test() {
+        // ~ SEED_RANDOM(5)
+        // {RANDOM(1, 6)} {RANDOM(1, 6)} {RANDOM(1, 6)}
+        let json = r###"{"inkVersion":21,"root":[["ev",5,"srnd","pop","/ev","ev",1,6,"rnd","out","/ev","^ ","ev",1,6,"rnd","out","/ev","^ ","ev",1,6,"rnd","out","/ev","\n","done",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("3 2 4\n"));
+    }
+
+    #[test]
+    fn invalid_random_range_test() {
+        let json = r###"{"inkVersion":21,"root":[["ev",6,1,"rnd","out","/ev","done",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+
+    #[test]
+    fn sequence_shuffle_index_test() {
+        // Shuffle indices of a 3 elements sequence in the container "0", for two full loops
+        let json = r###"{"inkVersion":21,"root":[["ev",5,"srnd","pop","/ev","ev",0,3,"seq","out",1,3,"seq","out",2,3,"seq","out",3,3,"seq","out",4,3,"seq","out",5,3,"seq","out","/ev","\n","done",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("120201\n"));
+    }
+
+    #[test]
+    fn list_random_test() {
+        // LIST colours = red, green, blue
+        // ~ SEED_RANDOM(5)
+        // {LIST_RANDOM(LIST_ALL(red))}
+        let json = r###"{"inkVersion":21,"root":[["ev",5,"srnd","pop","/ev","ev",{"list":{"colours.red":1,"colours.green":2,"colours.blue":3}},"lrnd","out","/ev","\n","ev",{"list":{}},"lrnd","out","/ev","done",null],"done",null],"listDefs":{"colours":{"red":1,"green":2,"blue":3}}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        // The first random number with seed 5 is 726643700, 726643700 % 3 = 2
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("blue\n"));
+    }
+
+    #[test]
+    fn tunnel_test() {
+        // Start
+        // -> tunnel ->
+        // Back
+        // -> tunnel -> other
+        // == tunnel ==
+        // In tunnel
+        // ->->
+        // == other ==
+        // Other
+        let json = r###"{"inkVersion":21,"root":[["^Start","\n",{"->t->":"tunnel"},"^Back","\n",{"->t->":"tunnel"},{"->":"other"},"end",null],"done",{"tunnel":["^In tunnel","\n","ev","void","/ev","->->",null],"other":["^Other","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Start\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In tunnel\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Back\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In tunnel\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Other\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn tunnel_onwards_divert_test() {
+        // -> tunnel ->
+        // Not shown
+        // == tunnel ==
+        // ->-> elsewhere
+        // == elsewhere ==
+        // Elsewhere
+        let json = r###"{"inkVersion":21,"root":[[{"->t->":"tunnel"},"^Not shown","\n","end",null],"done",{"tunnel":["ev",{"^->":"elsewhere"},"/ev","->->",null],"elsewhere":["^Elsewhere","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Elsewhere\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn function_test() {
+        // {add(1, 2)} and {greet()}.
+        // ~ log()
+        // Done
+        // == function add(a, b) ==
+        // ~ return a + b
+        // == function greet() ==
+        // Hello
+        // == function log() ==
+        // ~ temp a = 1
+        let json = r###"{"inkVersion":21,"root":[["ev",1,2,{"f()":"add"},"out","/ev","^ and ","ev",{"f()":"greet"},"out","/ev","^.","\n","ev",{"f()":"log"},"pop","/ev","^Done","\n","end",null],"done",{"add":[{"temp=":"b"},{"temp=":"a"},"ev",{"VAR?":"a"},{"VAR?":"b"},"+","/ev","~ret",null],"greet":["\n","^Hello","\n","ev","void","/ev","~ret",null],"log":["ev",1,"/ev",{"temp=":"a"},null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        // The whitespace around the output of a function is trimmed
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("3 and Hello.\n"));
+        // The temporary variables of a function do not leak out of it
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Done\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn external_function_test() {
+        // EXTERNAL add(a, b)
+        // {add(1, 2)}
+        let json = r###"{"inkVersion":21,"root":[["ev",1,2,{"x()":"add","exArgs":2},"out","/ev","\n","end",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        story.bind_external_function("add", |arguments| {
+            match (arguments[0].as_int(), arguments[1].as_int()) {
+                (Some(a), Some(b)) => Some(Value::Int(a + b)),
+                _ => None
+            }
+        }, true).unwrap();
+
+        assert!(story.bind_external_function("add", |_| None, true).is_err());
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("3\n"));
+
+        // Arguments which are not on the evaluation stack
+        let json = r###"{"inkVersion":21,"root":[["ev",1,{"x()":"add","exArgs":2},"out","/ev","\n","end",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        story.bind_external_function("add", |_| None, true).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "External function 'add' expects 2 arguments, but only 1 were given");
+    }
+
+    #[test]
+    fn external_function_fallback_test() {
+        // EXTERNAL greet()
+        // {greet()}
+        // == function greet() ==
+        // ~ return "Hello"
+        let json = r###"{"inkVersion":21,"root":[["ev",{"x()":"greet"},"out","/ev","\n","end",null],"done",{"greet":["ev","str","^Hello","/str","/ev","~ret",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "Trying to call EXTERNAL function 'greet' which has not been bound (and ink fallbacks disabled).");
+
+        let mut story = Story::from_str(json).unwrap();
+        story.set_allow_external_function_fallbacks(true);
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+
+        // A bound function takes precedence over the fallback
+        let mut story = Story::from_str(json).unwrap();
+        story.set_allow_external_function_fallbacks(true);
+        story.bind_external_function("greet", |_| Some(Value::String("Hi".to_owned())), true).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hi\n"));
+
+        story.unbind_external_function("greet").unwrap();
+        assert!(story.unbind_external_function("greet").is_err());
+    }
+
+    #[test]
+    fn external_function_lookahead_test() {
+        use std::cell::Cell;
+
+        // EXTERNAL play_sound()
+        // Line one
+        // ~ play_sound()
+        // Line two
+        let json = r###"{"inkVersion":21,"root":[["^Line one","\n","ev",{"x()":"play_sound"},"pop","/ev","^Line two","\n","end",null],"done",null],"listDefs":{}}"###;
+
+        for &lookahead_safe in &[false, true] {
+            let mut story = Story::from_str(json).unwrap();
+            let calls = Rc::new(Cell::new(0));
+            let counter = calls.clone();
+
+            story.bind_external_function("play_sound", move |_| {
+                counter.set(counter.get() + 1);
+                None
+            }, lookahead_safe).unwrap();
+
+            assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line one\n"));
+            // A function which is not lookahead safe is not called before its line is reached
+            assert_eq!(calls.get(), if lookahead_safe { 1 } else { 0 });
+
+            // The state is rewound after the lookahead, so a lookahead safe function is called again
+            assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Line two\n"));
+            assert_eq!(calls.get(), if lookahead_safe { 2 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn evaluate_function_test() {
+        // Hello
+        // * Choice
+        // -> END
+        // == function add(a, b) ==
+        // Adding {a} and {b}.
+        // ~ return a + b
+        // == function greet ==
+        // Hi!
+        let json = r###"{"inkVersion":21,"root":[["^Hello","\n","ev","str","^Choice","/str","/ev",{"*":"0.c-0","flg":20},{"c-0":["\n","end",{"#f":5}]}],"done",{"add":[{"temp=":"b"},{"temp=":"a"},"^Adding ","ev",{"VAR?":"a"},"out","/ev","^ and ","ev",{"VAR?":"b"},"out","/ev","^.","\n","ev",{"VAR?":"a"},{"VAR?":"b"},"+","/ev","~ret",null],"greet":["^Hi!","\n",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let (result, text) = story.evaluate_function("add", &[Value::Int(1), Value::Int(2)]).unwrap();
+        assert_eq!(result.unwrap().as_int(), Some(3));
+        assert_eq!(text, "Adding 1 and 2.\n");
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+
+        // The current line and the flow are not affected by the evaluation
+        let (result, text) = story.evaluate_function("greet", &[]).unwrap();
+        assert!(result.is_none());
+        assert_eq!(text, "Hi!\n");
+        assert_eq!(story.current_text(), Some("Hello\n"));
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+        story.evaluate_function("greet", &[]).unwrap();
+        assert_eq!(story.current_choices().unwrap().len(), 1);
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+
+        // Functions can still be evaluated once the story has ended
+        let (result, _) = story.evaluate_function("add", &[Value::Int(2), Value::Int(2)]).unwrap();
+        assert_eq!(result.unwrap().as_int(), Some(4));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+
+        let error = story.evaluate_function("subtract", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Function doesn't exist: 'subtract'");
+
+        let error = story.evaluate_function("greet.0", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Content at 'greet.0' is not a function");
+    }
+
+    #[test]
+    fn evaluate_knot_test() {
+        // Hello
+        // World
+        // == knot ==
+        // In knot
+        // -> END
+        let json = r###"{"inkVersion":21,"root":[["^Hello","\n","^World","\n","done",null],"done",{"knot":["^In knot","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+
+        let error = story.evaluate_function("knot", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Evaluation of 'knot' ended the story instead of returning, it may not be a function");
+
+        assert_eq!(story.current_text(), Some("Hello\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("World\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn failed_evaluate_function_test() {
+        // Hello
+        // World
+        // == function divide ==
+        // Dividing.
+        // ~ return 1 / 0
+        let json = r###"{"inkVersion":21,"root":[["^Hello","\n","^World","\n","done",null],"done",{"divide":["^Dividing.","\n","ev",1,0,"/","/ev","~ret",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+
+        // The story carries on as if the evaluation never happened
+        assert!(story.evaluate_function("divide", &[]).is_err());
+        assert_eq!(story.current_text(), Some("Hello\n"));
+        assert!(story.state.evaluation_stack().is_empty());
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("World\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn choose_path_test() {
+        // Start
+        // * Choice
+        // -> END
+        // == greet(name) ==
+        // Hello {name}!
+        // -> END
+        // == knot ==
+        // = stitch
+        // Stitch
+        // -> END
+        let json = r###"{"inkVersion":21,"root":[["^Start","\n","ev","str","^Choice","/str","/ev",{"*":"0.c-0","flg":20},{"c-0":["\n","end",{"#f":5}]}],"done",{"greet":[{"temp=":"name"},"^Hello ","ev",{"VAR?":"name"},"out","/ev","^!","\n","end",null],"knot":[{"stitch":["^Stitch","\n","end",{"#f":1}]}]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Start\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        // The current choices are dropped
+        story.choose_path("greet", &[Value::String("Bob".to_owned())]).unwrap();
+        assert!(story.current_choices().is_none());
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello Bob!\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+
+        story.choose_path("knot.stitch", &[]).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Stitch\n"));
+        assert_eq!(story.visit_count_at_path("knot.stitch").unwrap(), 1);
+
+        let error = story.choose_path("knot.unknown", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Failed to find content at path: knot.unknown");
+    }
+
+    // VAR x = 1
+    // Hello
+    // -> knot
+    // == knot ==
+    // ~ x = 2
+    // ~ temp y = 3
+    // * Choice {y}
+    //   Chosen {x} {y}
+    //   -> END
+    const SAVE_STATE_STORY: &str = r###"{"inkVersion":21,"root":[["^Hello","\n",{"->":"knot"},["done",{"#n":"g-0"}],null],"done",{"knot":["ev",2,"/ev",{"VAR=":"x","re":true},"ev",3,"/ev",{"temp=":"y"},"ev","str","^Choice ","ev",{"VAR?":"y"},"out","/ev","/str","/ev",{"*":".^.c-0","flg":20},{"c-0":["\n","^Chosen ","ev",{"VAR?":"x"},"out","/ev","^ ","ev",{"VAR?":"y"},"out","/ev","\n","end",{"#f":5}],"#f":1}],"global decl":["ev",1,{"VAR=":"x"},"/ev","end",null]}],"listDefs":{}}"###;
+
+    #[test]
+    fn save_load_state_test() {
+        let mut story = Story::from_str(SAVE_STATE_STORY).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        let save = story.save_state().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&save).unwrap();
+
+        assert_eq!(json["inkSaveVersion"], 10);
+        assert_eq!(json["currentFlowName"], "DEFAULT_FLOW");
+        assert_eq!(json["variablesState"]["x"], 2);
+        assert_eq!(json["visitCounts"]["knot"], 1);
+
+        // The thread of the choice is no longer active, it is saved along with the choice
+        let flow = &json["flows"]["DEFAULT_FLOW"];
+        assert_eq!(flow["currentChoices"][0]["text"], "Choice 3");
+        assert_eq!(flow["currentChoices"][0]["originalChoicePath"], "knot.17");
+        assert_eq!(flow["currentChoices"][0]["targetPath"], "knot.c-0");
+        assert_eq!(flow["choiceThreads"]["1"]["callstack"][0]["temp"]["y"], 3);
+
+        let mut loaded_story = Story::from_str(SAVE_STATE_STORY).unwrap();
+        loaded_story.load_state(&save).unwrap();
+
+        assert_eq!(loaded_story.save_state().unwrap(), save);
+        assert_eq!(loaded_story.current_choices().unwrap()[0].text(), Some("Choice 3"));
+
+        for story in &mut [story, loaded_story] {
+            story.make_choice(0).unwrap();
+            assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Chosen 2 3\n"));
+            assert_eq!(story.advance().unwrap(), StoryFlow::End);
+        }
+    }
+
+    #[test]
+    fn load_reference_state_test() {
+        // A save from a version of the reference runtime without multiple flows
+        let save = r###"{"callstackThreads":{"threads":[{"callstack":[{"exp":false,"type":0}],"threadIndex":0}],"threadCounter":1},"choiceThreads":{"1":{"callstack":[{"cPath":"knot","idx":17,"exp":false,"type":0,"temp":{"y":3}}],"threadIndex":1}},"currentChoices":[{"text":"Choice 3","index":0,"originalChoicePath":"knot.17","originalThreadIndex":1,"targetPath":"knot.c-0"}],"outputStream":[],"variablesState":{"x":2},"evalStack":[],"visitCounts":{"knot":1},"turnIndices":{},"turnIdx":-1,"storySeed":42,"previousRandom":0,"inkSaveVersion":8,"inkFormatVersion":20}"###;
+
+        let mut story = Story::from_str(SAVE_STATE_STORY).unwrap();
+        story.load_state(save).unwrap();
+
+        assert_eq!(story.variable("x").unwrap(), Value::Int(2));
+        assert_eq!(story.visit_count_at_path("knot").unwrap(), 1);
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Chosen 2 3\n"));
+    }
+
+    #[test]
+    fn load_invalid_state_test() {
+        let mut story = Story::from_str(SAVE_STATE_STORY).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+
+        let error = story.load_state("{}").unwrap_err();
+        assert_eq!(error.to_string(), "ink save format incorrect, can't load.");
+
+        let error = story.load_state(r#"{"inkSaveVersion":7}"#).unwrap_err();
+        assert_eq!(error.to_string(), "Ink save format isn't compatible with the current version (saw '7', but minimum is 8), so can't load.");
+
+        assert!(story.load_state(r#"{"inkSaveVersion":10,"flows":{}}"#).is_err());
+
+        // The state is left untouched
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+    }
+
+    #[test]
+    fn flows_test() {
+        // VAR x = 0
+        // == alice ==
+        // Alice: Hi
+        // * Bye
+        //   Alice: Bye
+        //   -> END
+        // == bob ==
+        // Bob: Yo
+        // ~ x = x + 1
+        // -> END
+        let json = r###"{"inkVersion":21,"root":[["done",null],"done",{"alice":["^Alice: Hi","\n","ev","str","^Bye","/str","/ev",{"*":".^.c-0","flg":20},{"c-0":["\n","^Alice: Bye","\n","end",{"#f":5}]}],"bob":["^Bob: Yo","\n","ev",{"VAR?":"x"},1,"+","/ev",{"VAR=":"x","re":true},"end",null],"global decl":["ev",0,{"VAR=":"x"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.current_flow_name(), "DEFAULT_FLOW");
+        assert!(story.alive_flow_names().is_empty());
+
+        story.choose_path("alice", &[]).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Alice: Hi\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        // A new flow has its own choices, but shares the globals
+        story.switch_flow("bob");
+        assert_eq!(story.current_flow_name(), "bob");
+        assert!(story.current_choices().is_none());
+
+        story.choose_path("bob", &[]).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Bob: Yo\n"));
+        assert_eq!(story.current_text(), Some("Bob: Yo\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+        assert_eq!(story.alive_flow_names(), vec!["bob".to_owned()]);
+
+        story.switch_flow("DEFAULT_FLOW");
+        assert_eq!(story.variable("x").unwrap(), Value::Int(1));
+        assert_eq!(story.current_choices().unwrap().len(), 1);
+
+        // The flows are saved along with the current flow name
+        let save = story.save_state().unwrap();
+        let saved_json: serde_json::Value = serde_json::from_str(&save).unwrap();
+        assert_eq!(saved_json["currentFlowName"], "DEFAULT_FLOW");
+        assert!(saved_json["flows"]["bob"].is_object());
+
+        let mut loaded_story = Story::from_str(json).unwrap();
+        loaded_story.load_state(&save).unwrap();
+        assert_eq!(loaded_story.current_flow_name(), "DEFAULT_FLOW");
+        assert_eq!(loaded_story.alive_flow_names(), vec!["bob".to_owned()]);
+
+        for story in &mut [story, loaded_story] {
+            story.make_choice(0).unwrap();
+            assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Alice: Bye\n"));
+
+            // Removing the current flow switches back to the default flow
+            story.switch_flow("bob");
+            story.remove_flow("bob").unwrap();
+            assert_eq!(story.current_flow_name(), "DEFAULT_FLOW");
+            assert!(story.alive_flow_names().is_empty());
+            assert_eq!(story.current_text(), Some("Alice: Bye\n"));
+
+            let error = story.remove_flow("DEFAULT_FLOW").unwrap_err();
+            assert_eq!(error.to_string(), "Cannot destroy default flow");
+        }
+    }
+
+    #[test]
+    fn mismatched_return_test() {
+        // A function return statement in a tunnel
+        let json = r###"{"inkVersion":21,"root":[[{"->t->":"tunnel"},"end",null],"done",{"tunnel":["ev","void","/ev","~ret",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "Found function return statement (~ return), when expected tunnel onwards statement (->->)");
+
+        // A tunnel onwards statement outside of a tunnel
+        let json = r###"{"inkVersion":21,"root":[["ev","void","/ev","->->","end",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "Found tunnel onwards statement (->->), when expected end of flow (-> END or choice)");
+    }
+
+    #[test]
+    fn threads_test() {
+        // <- a
+        // <- b
+        // Main
+        // -> DONE
+        // == a ==
+        // A text
+        // * [Choice A] Chose A
+        //   -> END
+        // == b ==
+        // * [Choice B] -> tunnel ->
+        //   Back in b
+        //   -> END
+        // == tunnel ==
+        // In tunnel
+        // ->->
+        let json = r###"{"inkVersion":21,"root":[["thread",{"->":"a"},"thread",{"->":"b"},"^Main","\n","done",null],"done",{"a":["^A text","\n","ev","str","^Choice A","/str","/ev",{"*":"a.c-0","flg":4},"done",{"c-0":["^Chose A","\n","end",null]}],"b":["ev","str","^Choice B","/str","/ev",{"*":"b.c-0","flg":4},"done",{"c-0":[{"->t->":"tunnel"},"^Back in b","\n","end",null]}],"tunnel":["^In tunnel","\n","ev","void","/ev","->->",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        // The threads are done without ending the story
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("A text\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Main\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        {
+            let choices = story.current_choices().unwrap();
+            assert_eq!(choices.len(), 2);
+            assert_eq!(choices[0].text(), Some("Choice A"));
+            assert_eq!(choices[1].text(), Some("Choice B"));
+        }
+
+        // The choice resumes in the thread which generated it
+        story.make_choice(1).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In tunnel\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Back in b\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn variable_divert_test() {
+        // VAR target = -> knot
+        // -> target
+        // == knot ==
+        // In knot
+        let json = r###"{"inkVersion":21,"root":[[{"->":"target","var":true},"done",null],"done",{"knot":["^In knot","\n","end",null],"global decl":["ev",{"^->":"knot"},{"VAR=":"target"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In knot\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn invalid_variable_divert_test() {
+        // VAR target = 0
+        let json = r###"{"inkVersion":21,"root":[[{"->":"target","var":true},"done",null],"done",{"global decl":["ev",0,{"VAR=":"target"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "Tried to divert to a target from a variable, but the variable (target) didn't contain a divert target, it was empty/null (the value 0).");
+
+        let json = r###"{"inkVersion":21,"root":[[{"->":"unknown","var":true},"done",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        let error = story.advance().unwrap_err();
+        assert_eq!(error.to_string(), "Tried to divert using a target from a variable that could not be found (unknown)");
+    }
+
+    #[test]
+    fn conditional_divert_test() {
+        // {false: -> skipped}
+        // {true: -> knot}
+        // == skipped ==
+        // Skipped
+        // == knot ==
+        // In knot
+        let json = r###"{"inkVersion":21,"root":[["ev",false,"/ev",{"->":"skipped","c":true},"ev",1,"/ev",{"->":"knot","c":true},"done",null],"done",{"skipped":["^Skipped","\n","end",null],"knot":["^In knot","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In knot\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn relative_paths_test() {
+        // -> knot
+        // == knot ==
+        // {&Tick|Tock} {knot}
+        // + [Again] -> knot
+        // + [Stop] -> END
+        let json = r###"{"inkVersion":21,"root":[[{"->":"knot"},["done",{"#n":"g-0"}],null],"done",{"knot":[["ev","visit",2,"%","/ev","ev","du",0,"==","/ev",{"->":".^.s0","c":true},"ev","du",1,"==","/ev",{"->":".^.s1","c":true},"nop",{"s0":["pop","^Tick",{"->":".^.^.17"},null],"s1":["pop","^Tock",{"->":".^.^.17"},null],"#f":5}],"^ ","ev",{"CNT?":".^"},"out","/ev","\n","ev","str","^Again","/str","/ev",{"*":".^.c-0","flg":4},"ev","str","^Stop","/str","/ev",{"*":".^.c-1","flg":4},{"c-0":["\n",{"->":"knot"},null],"c-1":["\n","end",null],"#f":5}]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Tick 1\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Tock 2\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        story.make_choice(0).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Tick 3\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        story.make_choice(1).unwrap();
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn relative_path_above_root_test() {
+        let json = r###"{"inkVersion":21,"root":[[{"->":".^.^.^.0"},"done",null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+
+    #[test]
+    fn assign_undeclared_temporary_test() {
+        let json = r###"{"inkVersion":17,"root":[["ev",2,"/ev",{"temp=":"x","re":true},"done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+
+    #[test]
+    fn glue_test() {
+        // Hello <>
+        // -> next
+        // == next ==
+        // <> world
+        // A
+        // <> B
+        let json = r###"{"inkVersion":17,"root":[["^Hello ","<>","\n",{"->":"next"},null],"done",{"next":["<>","^world","\n","^A","\n","<>","^B","\n","^C","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello world\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("AB\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("C\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn whitespace_test() {
+        let json = r###"{"inkVersion":17,"root":[["^  Hello   ","^  world  ","\n","\n","^\nA line\n  ","^\tAnother\t line","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello world\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("A line\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Another line\n"));
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+    }
+
+    #[test]
+    fn tags_test() {
+        // # author: Joe
+        // # title: Tags
+        // Hello # greeting
+        // # above
+        // World
+        // -> knot
+        // == knot ==
+        // # knot_tag
+        // In the knot
+        let json = r###"{"inkVersion":17,"root":[[{"#":"author: Joe"},{"#":"title: Tags"},"^Hello ",{"#":"greeting"},"\n",{"#":"above"},"^World","\n",{"->":"knot"},null],"done",{"knot":[{"#":"knot_tag"},"^In the knot","\n","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.global_tags(), vec!["author: Joe".to_owned(), "title: Tags".to_owned()]);
+        assert_eq!(story.tags_for_content_at_path("knot").unwrap(), vec!["knot_tag".to_owned()]);
+        assert!(story.tags_for_content_at_path("unknown").is_err());
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+        assert_eq!(story.current_tags(), &vec!["author: Joe".to_owned(), "title: Tags".to_owned(), "greeting".to_owned()]);
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("World\n"));
+        assert_eq!(story.current_tags(), &vec!["above".to_owned()]);
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("In the knot\n"));
+        assert_eq!(story.current_tags(), &vec!["knot_tag".to_owned()]);
+        assert_eq!(story.advance().unwrap(), StoryFlow::End);
+        assert!(story.current_tags().is_empty());
+
+        // Tags output during a string evaluation are kept for the line
+        let json = r###"{"inkVersion":17,"root":[["^Hello ","ev","str","^World",{"#":"in_string"},"/str","out","/ev","\n","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello World\n"));
+        assert_eq!(story.current_tags(), &vec!["in_string".to_owned()]);
+    }
+
+    #[test]
+    fn dynamic_tags_test() {
+        // # author: Joe
+        // VAR mood = "happy"
+        // Hello # mood_{mood}
+        // * Choice # chosen_{mood}
+        //   -> END
+        let json = r###"{"inkVersion":21,"root":[["#","^author: Joe","/#","^Hello ","#","^mood_","ev",{"VAR?":"mood"},"out","/ev","/#","\n","ev","str","^Choice ","#","^chosen_","ev",{"VAR?":"mood"},"out","/ev","/#","/str","/ev",{"*":"0.c-0","flg":20},{"c-0":["\n","end",{"#f":5}]}],"done",{"global decl":["ev","str","^happy","/str",{"VAR=":"mood"},"/ev","end",null]}],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert_eq!(story.global_tags(), vec!["author: Joe".to_owned()]);
+
+        assert_eq!(story.advance().unwrap(), StoryFlow::Continue("Hello\n"));
+        assert_eq!(story.current_tags(), &vec!["author: Joe".to_owned(), "mood_happy".to_owned()]);
+        assert_eq!(story.advance().unwrap(), StoryFlow::WaitForChoice);
+
+        let choices = story.current_choices().unwrap();
+        assert_eq!(choices[0].text(), Some("Choice"));
+        assert_eq!(choices[0].tags(), &vec!["chosen_happy".to_owned()]);
+    }
+
+    #[test]
+    fn empty_evaluation_stack_test() {
+        let json = r###"{"inkVersion":17,"root":[["ev","out","/ev","done",null],null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+
+    #[test]
+    fn divert_to_unknown_path_test() {
+        let json = r###"{"inkVersion":17,"root":[[{"->":"unknown"},null],"done",null],"listDefs":{}}"###;
+        let mut story = Story::from_str(json).unwrap();
+
+        assert!(story.advance().is_err());
+    }
+}
@@ -1,28 +1,830 @@
 use callstack::CallStack;
 use choice::Choice;
+use error::InkError;
+use json_parser::{read_array, read_field, read_int, read_object, read_runtime_objects, read_str};
+use json_writer::write_runtime_objects;
+use prng::Prng;
 use runtime_graph::RuntimeGraph;
 use runtime::RuntimeObject;
+use runtime::container::Container;
+use runtime::control_command::ControlCommand;
+use runtime::divert::PushPopType;
+use runtime::value::Value;
+use runtime::variable::VariableAssignment;
+use runtime_context::RuntimeContext;
+use story::INK_VERSION;
+use variables_state::VariablesState;
 
+use serde_json;
+use serde_json::Map;
+
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub struct StoryState {
+/// Version of the JSON format of the saved states, as written by the reference ink runtime.
+pub const INK_SAVE_VERSION: u32 = 10;
+pub const INK_SAVE_VERSION_MINIMUM_COMPATIBLE: u32 = 8;
+
+pub const DEFAULT_FLOW_NAME: &str = "DEFAULT_FLOW";
+
+/// An independent flow of the story, with its own callstack, output and choices. The flows share
+/// the globals and the visit counts.
+#[derive(Clone)]
+struct Flow {
+    name: String,
     callstack: CallStack,
+    output_stream: Vec<RuntimeObject>,
     choices: Vec<Choice>
 }
 
+impl Flow {
+    fn new(name: &str, root_container: &Rc<Container>) -> Flow {
+        Flow {
+            name: name.to_owned(),
+            callstack: CallStack::new(root_container),
+            output_stream: Vec::new(),
+            choices: Vec::new()
+        }
+    }
+
+    fn to_json(&self) -> Result<serde_json::Value, InkError> {
+        let mut json = Map::new();
+        json.insert("callstack".to_owned(), self.callstack.to_json());
+        json.insert("outputStream".to_owned(), write_runtime_objects(&self.output_stream)?);
+
+        // The threads of the choices are only written when they are no longer active
+        let mut choice_threads = Map::new();
+        for thread in self.choices.iter().map(|choice| choice.thread()) {
+            if self.callstack.thread_with_index(thread.index()).is_none() {
+                choice_threads.insert(thread.index().to_string(), thread.to_json());
+            }
+        }
+
+        if !choice_threads.is_empty() {
+            json.insert("choiceThreads".to_owned(), serde_json::Value::Object(choice_threads));
+        }
+
+        let mut visible_choice_index = 0;
+        let mut choices = Vec::with_capacity(self.choices.len());
+        for choice in &self.choices {
+            choices.push(choice.to_json(visible_choice_index));
+
+            if !choice.is_invisible_default() {
+                visible_choice_index += 1;
+            }
+        }
+
+        json.insert("currentChoices".to_owned(), serde_json::Value::Array(choices));
+        Ok(serde_json::Value::Object(json))
+    }
+
+    /// Read a flow written by `to_json`. Older saves have a single flow, written at the top level
+    /// of the state with a different name for the callstack.
+    fn from_json(name: &str, json: &serde_json::Value, callstack_key: &str, runtime_graph: &RuntimeGraph) -> Result<Flow, InkError> {
+        let callstack = CallStack::from_json(read_field(json, callstack_key)?, runtime_graph.root_container())?;
+        let output_stream = read_runtime_objects(read_field(json, "outputStream")?)?;
+
+        let mut choices = Vec::new();
+        for choice in read_array(json, "currentChoices")? {
+            choices.push(Choice::from_json(choice, runtime_graph, &callstack, json.get("choiceThreads"))?);
+        }
+
+        Ok(Flow {
+            name: name.to_owned(),
+            callstack: callstack,
+            output_stream: output_stream,
+            choices: choices
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct StoryState {
+    current_flow: Flow,
+    /// The flows other than the current flow, keyed by name.
+    flows: HashMap<String, Flow>,
+    evaluation_stack: Vec<RuntimeObject>,
+    variables_state: VariablesState,
+    visit_counts: HashMap<String, i32>,
+    turn_indices: HashMap<String, i32>,
+    current_turn_index: i32,
+    story_seed: i32,
+    previous_random: i32,
+    followed_default_choice: Option<Choice>,
+    /// Whether the flow was stopped on purpose, by a DONE or an END, rather than by running out
+    /// of content.
+    did_safe_exit: bool
+}
+
 impl StoryState {
     pub fn new(runtime_graph: &RuntimeGraph) -> StoryState {
         StoryState {
-            callstack: CallStack::new(runtime_graph.root_container()),
-            choices: Vec::new()
+            current_flow: Flow::new(DEFAULT_FLOW_NAME, runtime_graph.root_container()),
+            flows: HashMap::new(),
+            evaluation_stack: Vec::new(),
+            variables_state: VariablesState::new(),
+            visit_counts: HashMap::new(),
+            turn_indices: HashMap::new(),
+            current_turn_index: -1,
+            story_seed: initial_story_seed(),
+            previous_random: 0,
+            followed_default_choice: None,
+            did_safe_exit: false
         }
     }
 
     pub fn callstack(&mut self) -> &mut CallStack {
-        &mut self.callstack
+        &mut self.current_flow.callstack
+    }
+
+    pub fn current_flow_name(&self) -> &str {
+        &self.current_flow.name
+    }
+
+    /// Get the names of the flows which have been started, except the default flow which is
+    /// always alive.
+    pub fn alive_flow_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.flows.keys()
+            .chain(Some(&self.current_flow.name))
+            .filter(|name| name.as_str() != DEFAULT_FLOW_NAME)
+            .cloned()
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    /// Make the flow with the given name the current flow, starting it at the root of the story
+    /// if it does not exist yet.
+    pub fn switch_flow(&mut self, name: &str, root_container: &Rc<Container>) {
+        if name == self.current_flow.name {
+            return;
+        }
+
+        let flow = match self.flows.remove(name) {
+            Some(flow) => flow,
+            _ => Flow::new(name, root_container)
+        };
+
+        let previous_flow = ::std::mem::replace(&mut self.current_flow, flow);
+        self.flows.insert(previous_flow.name.clone(), previous_flow);
+    }
+
+    /// Remove the flow with the given name, switching back to the default flow if it is the
+    /// current flow.
+    pub fn remove_flow(&mut self, name: &str, root_container: &Rc<Container>) -> Result<(), InkError> {
+        if name == DEFAULT_FLOW_NAME {
+            return Err(InkError::from_message("Cannot destroy default flow"));
+        }
+
+        if name == self.current_flow.name {
+            self.switch_flow(DEFAULT_FLOW_NAME, root_container);
+        }
+
+        self.flows.remove(name);
+        Ok(())
+    }
+
+    pub fn choices(&self) -> &Vec<Choice> {
+        &self.current_flow.choices
+    }
+
+    /// Get the choices which can be presented to the player, that is all the generated choices
+    /// except the invisible default ones.
+    pub fn visible_choices(&self) -> Vec<&Choice> {
+        self.current_flow.choices.iter().filter(|choice| !choice.is_invisible_default()).collect()
+    }
+
+    pub fn add_choice(&mut self, choice: Choice) {
+        self.current_flow.choices.push(choice);
+    }
+
+    pub fn clear_choices(&mut self) {
+        self.current_flow.choices.clear();
+    }
+
+    pub fn set_choices(&mut self, choices: Vec<Choice>) {
+        self.current_flow.choices = choices;
+    }
+
+    pub fn followed_default_choice(&self) -> Option<&Choice> {
+        self.followed_default_choice.as_ref()
+    }
+
+    pub fn set_followed_default_choice(&mut self, choice: Choice) {
+        self.followed_default_choice = Some(choice);
+    }
+
+    pub fn clear_followed_default_choice(&mut self) {
+        self.followed_default_choice = None;
+    }
+
+    pub fn did_safe_exit(&self) -> bool {
+        self.did_safe_exit
+    }
+
+    pub fn set_did_safe_exit(&mut self, did_safe_exit: bool) {
+        self.did_safe_exit = did_safe_exit;
+    }
+
+    /// Get the number of times the container at the given path has been visited.
+    pub fn visit_count_at_path(&self, path: &str) -> i32 {
+        self.visit_counts.get(path).cloned().unwrap_or(0)
+    }
+
+    pub fn increment_visit_count(&mut self, path: &str) {
+        *self.visit_counts.entry(path.to_owned()).or_insert(0) += 1;
+    }
+
+    /// Get the number of turns since the container at the given path was last visited, or -1 if
+    /// it has never been visited.
+    pub fn turns_since_at_path(&self, path: &str) -> i32 {
+        match self.turn_indices.get(path) {
+            Some(&turn_index) => self.current_turn_index - turn_index,
+            _ => -1
+        }
+    }
+
+    /// Record that the container at the given path has been visited during the current turn.
+    pub fn record_turn_index_visit(&mut self, path: &str) {
+        self.turn_indices.insert(path.to_owned(), self.current_turn_index);
+    }
+
+    pub fn increment_turn_index(&mut self) {
+        self.current_turn_index += 1;
+    }
+
+    /// Get the seed used by RANDOM and by shuffle sequences.
+    pub fn story_seed(&self) -> i32 {
+        self.story_seed
+    }
+
+    /// Seed the random numbers, as done by SEED_RANDOM in ink.
+    pub fn set_story_seed(&mut self, story_seed: i32) {
+        self.story_seed = story_seed;
+        self.previous_random = 0;
+    }
+
+    /// Get the next random number. The generator is not kept around: it is seeded again with
+    /// the story seed and the previous random number so that the state can be saved as is.
+    pub fn next_random(&mut self) -> i32 {
+        let next_random = Prng::new(self.story_seed.wrapping_add(self.previous_random)).next();
+        self.previous_random = next_random;
+        next_random
+    }
+
+    pub fn variables_state(&self) -> &VariablesState {
+        &self.variables_state
+    }
+
+    pub fn variables_state_mut(&mut self) -> &mut VariablesState {
+        &mut self.variables_state
+    }
+
+    /// Get the value of a variable visible from the current callstack element.
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        self.variables_state.variable(name, -1, &self.current_flow.callstack)
+    }
+
+    pub fn assign(&mut self, variable_assignment: &VariableAssignment, value: Value) -> Result<(), InkError> {
+        self.variables_state.assign(variable_assignment, value, &mut self.current_flow.callstack)
+    }
+
+    /// Get the context index of a variable visible from the current callstack element, see
+    /// `VariablesState`.
+    pub fn context_for_variable_named(&self, name: &str) -> i32 {
+        self.current_flow.callstack.context_for_variable_named(name)
     }
 
     pub fn end_of_story(&self) -> bool {
-        return self.callstack.runtime_object().is_none();
+        return self.current_flow.callstack.runtime_object().is_none();
+    }
+
+    pub fn in_expression_evaluation(&self) -> bool {
+        match self.current_flow.callstack.runtime_context() {
+            Some(runtime_context) => runtime_context.in_expression_evaluation(),
+            _ => false
+        }
+    }
+
+    pub fn set_in_expression_evaluation(&mut self, in_expression_evaluation: bool) {
+        if let Some(runtime_context) = self.current_flow.callstack.runtime_context_mut() {
+            runtime_context.set_in_expression_evaluation(in_expression_evaluation);
+        }
+    }
+
+    pub fn evaluation_stack(&self) -> &Vec<RuntimeObject> {
+        &self.evaluation_stack
+    }
+
+    pub fn push_evaluation_stack(&mut self, runtime_object: RuntimeObject) {
+        self.evaluation_stack.push(runtime_object);
+    }
+
+    pub fn pop_evaluation_stack(&mut self) -> Option<RuntimeObject> {
+        self.evaluation_stack.pop()
+    }
+
+    pub fn peek_evaluation_stack(&self) -> Option<&RuntimeObject> {
+        self.evaluation_stack.last()
+    }
+
+    pub fn truncate_evaluation_stack(&mut self, height: usize) {
+        self.evaluation_stack.truncate(height);
+    }
+
+    pub fn output_stream(&self) -> &Vec<RuntimeObject> {
+        &self.current_flow.output_stream
+    }
+
+    /// Append an object to the output stream. Strings starting or ending with newlines are
+    /// split so that the newlines are individual objects which can be removed by glue.
+    pub fn push_to_output_stream(&mut self, runtime_object: RuntimeObject) {
+        if let RuntimeObject::Value(Value::String(ref text)) = runtime_object {
+            if let Some(texts) = split_head_tail_whitespace(text) {
+                for text in texts {
+                    self.push_to_output_stream_individual(RuntimeObject::Value(Value::String(text)));
+                }
+
+                return;
+            }
+        }
+
+        self.push_to_output_stream_individual(runtime_object);
+    }
+
+    fn push_to_output_stream_individual(&mut self, runtime_object: RuntimeObject) {
+        let mut include_in_output = true;
+
+        match runtime_object {
+            // New glue, chomp away any whitespace from the end of the stream
+            RuntimeObject::Glue(_) => self.trim_newlines_from_output_stream(),
+            RuntimeObject::Value(Value::String(ref text)) => {
+                // The whitespace at the start of a function is trimmed
+                let mut function_trim_index = match self.current_flow.callstack.runtime_context() {
+                    Some(runtime_context) if runtime_context.stack_push_type() == PushPopType::Function => {
+                        runtime_context.function_start_in_output_stream()
+                    },
+                    _ => None
+                };
+
+                // Find the latest glue, without looking past the start of a string evaluation. A
+                // string evaluated within the function is not trimmed past its start either.
+                let mut glue_trim_index = None;
+                for (i, runtime_object) in self.current_flow.output_stream.iter().enumerate().rev() {
+                    match runtime_object {
+                        &RuntimeObject::Glue(_) => {
+                            glue_trim_index = Some(i);
+                            break;
+                        },
+                        &RuntimeObject::ControlCommand(ControlCommand::BeginString) => {
+                            if function_trim_index.is_some_and(|function_trim_index| i >= function_trim_index) {
+                                function_trim_index = None;
+                            }
+
+                            break;
+                        },
+                        _ => {}
+                    }
+                }
+
+                if glue_trim_index.is_some() || function_trim_index.is_some() {
+                    // While trimming, all the newlines are thrown away until proper text is pushed
+                    if text == "\n" {
+                        include_in_output = false;
+                    } else if is_non_whitespace(text) {
+                        if glue_trim_index.is_some() {
+                            self.remove_existing_glue();
+                        }
+
+                        if function_trim_index.is_some() {
+                            self.current_flow.callstack.clear_function_start_in_output_stream();
+                        }
+                    }
+                } else if text == "\n" && (self.output_stream_ends_in_newline() || !self.output_stream_contains_content()) {
+                    // De-duplicate newlines, and never lead with a newline
+                    include_in_output = false;
+                }
+            },
+            _ => {}
+        }
+
+        if include_in_output {
+            self.current_flow.output_stream.push(runtime_object);
+        }
     }
-}
\ No newline at end of file
+
+    /// Remove the trailing newlines, and the whitespace following them, from the output stream.
+    fn trim_newlines_from_output_stream(&mut self) {
+        let mut remove_whitespace_from = None;
+
+        for (i, runtime_object) in self.current_flow.output_stream.iter().enumerate().rev() {
+            match runtime_object {
+                &RuntimeObject::ControlCommand(_) => break,
+                &RuntimeObject::Value(Value::String(ref text)) if is_non_whitespace(text) => break,
+                &RuntimeObject::Value(Value::String(ref text)) if text == "\n" => remove_whitespace_from = Some(i),
+                _ => {}
+            }
+        }
+
+        if let Some(index) = remove_whitespace_from {
+            let mut i = index;
+            while i < self.current_flow.output_stream.len() {
+                match self.current_flow.output_stream[i] {
+                    RuntimeObject::Value(Value::String(_)) => { self.current_flow.output_stream.remove(i); },
+                    _ => i += 1
+                }
+            }
+        }
+    }
+
+    /// Pop the current element of the callstack. At the end of a function, the whitespace it
+    /// output at the end is trimmed.
+    pub fn pop_callstack(&mut self) -> Option<RuntimeContext> {
+        if self.current_flow.callstack.current_stack_push_type() == Some(PushPopType::Function) {
+            self.trim_whitespace_from_function_end();
+        }
+
+        self.current_flow.callstack.pop()
+    }
+
+    fn trim_whitespace_from_function_end(&mut self) {
+        // Once some text has been output, trim as far back as possible
+        let function_start = self.current_flow.callstack.runtime_context()
+            .and_then(|runtime_context| runtime_context.function_start_in_output_stream())
+            .unwrap_or(0);
+
+        let mut i = self.current_flow.output_stream.len();
+        while i > function_start {
+            i -= 1;
+
+            match self.current_flow.output_stream[i] {
+                RuntimeObject::Value(Value::String(ref text)) if text == "\n" || text.chars().all(is_inline_whitespace) => {},
+                RuntimeObject::Value(Value::String(_)) => break,
+                _ => continue
+            }
+
+            self.current_flow.output_stream.remove(i);
+        }
+    }
+
+    fn remove_existing_glue(&mut self) {
+        let mut i = self.current_flow.output_stream.len();
+        while i > 0 {
+            i -= 1;
+
+            match self.current_flow.output_stream[i] {
+                RuntimeObject::Glue(_) => { self.current_flow.output_stream.remove(i); },
+                RuntimeObject::ControlCommand(_) => break,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn pop_from_output_stream(&mut self, count: usize) {
+        let len = self.current_flow.output_stream.len();
+        self.current_flow.output_stream.truncate(len - count.min(len));
+    }
+
+    /// Returns true if the output stream ends with a newline, ignoring the trailing inline
+    /// whitespace.
+    pub fn output_stream_ends_in_newline(&self) -> bool {
+        for runtime_object in self.current_flow.output_stream.iter().rev() {
+            match runtime_object {
+                &RuntimeObject::ControlCommand(_) => break,
+                &RuntimeObject::Value(Value::String(ref text)) if text == "\n" => return true,
+                &RuntimeObject::Value(Value::String(ref text)) if is_non_whitespace(text) => break,
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    pub fn output_stream_contains_content(&self) -> bool {
+        self.current_flow.output_stream.iter().any(|runtime_object| match runtime_object {
+            &RuntimeObject::Value(Value::String(_)) => true,
+            _ => false
+        })
+    }
+
+    /// Returns true while evaluating a string, that is after a `BeginString` marker has been
+    /// pushed to the output stream and before the matching `EndString`.
+    pub fn in_string_evaluation(&self) -> bool {
+        self.current_flow.output_stream.iter().any(|runtime_object| match runtime_object {
+            &RuntimeObject::ControlCommand(ControlCommand::BeginString) => true,
+            _ => false
+        })
+    }
+
+    /// Concatenate the text content of the output stream, collapsing the inline whitespace. The
+    /// content of dynamic tags is not part of the text.
+    pub fn current_text(&self) -> String {
+        let mut text = String::new();
+        let mut in_tag = false;
+
+        for runtime_object in self.current_flow.output_stream.iter() {
+            match runtime_object {
+                &RuntimeObject::Value(Value::String(ref content)) if !in_tag => text.push_str(content),
+                &RuntimeObject::ControlCommand(ControlCommand::BeginTag) => in_tag = true,
+                &RuntimeObject::ControlCommand(ControlCommand::EndTag) => in_tag = false,
+                _ => {}
+            }
+        }
+
+        clean_output_whitespace(&text)
+    }
+
+    /// Get the tags of the output stream, both static tags and the dynamic tags whose text is
+    /// output between `BeginTag` and `EndTag` markers.
+    pub fn current_tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        let mut in_tag = false;
+        let mut tag_text = String::new();
+
+        for runtime_object in self.current_flow.output_stream.iter() {
+            match runtime_object {
+                &RuntimeObject::ControlCommand(ControlCommand::BeginTag) => {
+                    if in_tag && !tag_text.is_empty() {
+                        tags.push(clean_output_whitespace(&tag_text));
+                        tag_text.clear();
+                    }
+
+                    in_tag = true;
+                },
+                &RuntimeObject::ControlCommand(ControlCommand::EndTag) => {
+                    if !tag_text.is_empty() {
+                        tags.push(clean_output_whitespace(&tag_text));
+                        tag_text.clear();
+                    }
+
+                    in_tag = false;
+                },
+                &RuntimeObject::Value(Value::String(ref text)) if in_tag => tag_text.push_str(text),
+                &RuntimeObject::Tag(ref tag) if !tag.text().is_empty() => {
+                    if in_tag {
+                        tag_text.push_str(tag.text());
+                    } else {
+                        tags.push(tag.text().clone());
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        if !tag_text.is_empty() {
+            tags.push(clean_output_whitespace(&tag_text));
+        }
+
+        tags
+    }
+
+    pub fn reset_output(&mut self) {
+        self.current_flow.output_stream.clear();
+    }
+
+    pub fn set_output_stream(&mut self, output_stream: Vec<RuntimeObject>) {
+        self.current_flow.output_stream = output_stream;
+    }
+
+    /// Write the state in the JSON format of the reference ink runtime, so that a story can be
+    /// saved by one runtime and loaded by another.
+    pub fn to_json(&self) -> Result<serde_json::Value, InkError> {
+        let mut flows = Map::new();
+        for flow in self.flows.values().chain(Some(&self.current_flow)) {
+            flows.insert(flow.name.clone(), flow.to_json()?);
+        }
+
+        let mut json = Map::new();
+        json.insert("flows".to_owned(), serde_json::Value::Object(flows));
+        json.insert("currentFlowName".to_owned(), serde_json::Value::String(self.current_flow.name.clone()));
+        json.insert("variablesState".to_owned(), self.variables_state.to_json());
+        json.insert("evalStack".to_owned(), write_runtime_objects(&self.evaluation_stack)?);
+        json.insert("visitCounts".to_owned(), write_counts(&self.visit_counts));
+        json.insert("turnIndices".to_owned(), write_counts(&self.turn_indices));
+        json.insert("turnIdx".to_owned(), serde_json::Value::Number(self.current_turn_index.into()));
+        json.insert("storySeed".to_owned(), serde_json::Value::Number(self.story_seed.into()));
+        json.insert("previousRandom".to_owned(), serde_json::Value::Number(self.previous_random.into()));
+        json.insert("inkSaveVersion".to_owned(), serde_json::Value::Number(INK_SAVE_VERSION.into()));
+        json.insert("inkFormatVersion".to_owned(), serde_json::Value::Number(INK_VERSION.into()));
+
+        Ok(serde_json::Value::Object(json))
+    }
+
+    /// Replace the state by a state written by `to_json`, or by the reference ink runtime. The
+    /// state is left untouched if the JSON cannot be read.
+    pub fn load_json(&mut self, json: &serde_json::Value, runtime_graph: &RuntimeGraph) -> Result<(), InkError> {
+        let save_version = match json.get("inkSaveVersion").and_then(|save_version| save_version.as_i64()) {
+            Some(save_version) => save_version,
+            _ => return Err(InkError::from_message("ink save format incorrect, can't load."))
+        };
+
+        if save_version < INK_SAVE_VERSION_MINIMUM_COMPATIBLE as i64 {
+            return Err(InkError::from_message(format!("Ink save format isn't compatible with the current version (saw '{}', but minimum is {}), so can't load.", save_version, INK_SAVE_VERSION_MINIMUM_COMPATIBLE)));
+        }
+
+        let mut flows = HashMap::new();
+        if json.get("flows").is_some() {
+            for (name, flow) in read_object(json, "flows")? {
+                flows.insert(name.clone(), Flow::from_json(name, flow, "callstack", runtime_graph)?);
+            }
+        } else {
+            // Older saves have a single flow, written at the top level
+            flows.insert(DEFAULT_FLOW_NAME.to_owned(), Flow::from_json(DEFAULT_FLOW_NAME, json, "callstackThreads", runtime_graph)?);
+        }
+
+        // A single flow is the current flow, whatever its name
+        let current_flow_name = match flows.keys().next() {
+            Some(name) if flows.len() == 1 => name.clone(),
+            _ => read_str(json, "currentFlowName")?.to_owned()
+        };
+
+        let current_flow = match flows.remove(&current_flow_name) {
+            Some(flow) => flow,
+            _ => return Err(InkError::from_message(format!("Missing current flow '{}' in the story state", current_flow_name)))
+        };
+
+        let mut variables_state = self.variables_state.clone();
+        variables_state.load_json(read_field(json, "variablesState")?)?;
+
+        let evaluation_stack = read_runtime_objects(read_field(json, "evalStack")?)?;
+        let visit_counts = read_counts(json, "visitCounts")?;
+        let turn_indices = read_counts(json, "turnIndices")?;
+        let current_turn_index = read_int(json, "turnIdx")? as i32;
+        let story_seed = read_int(json, "storySeed")? as i32;
+        let previous_random = read_int(json, "previousRandom")? as i32;
+
+        self.current_flow = current_flow;
+        self.flows = flows;
+        self.evaluation_stack = evaluation_stack;
+        self.variables_state = variables_state;
+        self.visit_counts = visit_counts;
+        self.turn_indices = turn_indices;
+        self.current_turn_index = current_turn_index;
+        self.story_seed = story_seed;
+        self.previous_random = previous_random;
+        self.followed_default_choice = None;
+        self.did_safe_exit = false;
+
+        Ok(())
+    }
+}
+
+fn write_counts(counts: &HashMap<String, i32>) -> serde_json::Value {
+    serde_json::Value::Object(counts.iter().map(|(path, &count)| (path.clone(), serde_json::Value::Number(count.into()))).collect())
+}
+
+fn read_counts(json: &serde_json::Value, key: &str) -> Result<HashMap<String, i32>, InkError> {
+    let mut counts = HashMap::new();
+
+    for (path, count) in read_object(json, key)? {
+        match count.as_i64() {
+            Some(count) => counts.insert(path.clone(), count as i32),
+            _ => return Err(InkError::from_message(format!("Expected an integer for '{}' in '{}' in the story state", path, key)))
+        };
+    }
+
+    Ok(counts)
+}
+
+fn is_inline_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_non_whitespace(text: &str) -> bool {
+    text.chars().any(|c| !is_inline_whitespace(c) && c != '\n')
+}
+
+/// Split a string into its leading newline, its inner text and its trailing newline, keeping
+/// the inline whitespace before the first newline and after the last one. Returns None if the
+/// string neither starts nor ends with a newline (ignoring inline whitespace).
+fn split_head_tail_whitespace(text: &str) -> Option<Vec<String>> {
+    let bytes = text.as_bytes();
+
+    let mut head_first_newline = None;
+    let mut head_last_newline = None;
+    for (i, &c) in bytes.iter().enumerate() {
+        match c {
+            b'\n' => {
+                head_first_newline = head_first_newline.or(Some(i));
+                head_last_newline = Some(i);
+            },
+            b' ' | b'\t' => continue,
+            _ => break
+        }
+    }
+
+    let mut tail_last_newline = None;
+    let mut tail_first_newline = None;
+    for (i, &c) in bytes.iter().enumerate().rev() {
+        match c {
+            b'\n' => {
+                tail_last_newline = tail_last_newline.or(Some(i));
+                tail_first_newline = Some(i);
+            },
+            b' ' | b'\t' => continue,
+            _ => break
+        }
+    }
+
+    if head_first_newline.is_none() && tail_last_newline.is_none() {
+        return None;
+    }
+
+    let mut texts = Vec::new();
+    let mut inner_start = 0;
+    let mut inner_end = text.len();
+
+    if let (Some(first), Some(last)) = (head_first_newline, head_last_newline) {
+        if first > 0 {
+            texts.push(text[..first].to_owned());
+        }
+
+        texts.push("\n".to_owned());
+        inner_start = last + 1;
+    }
+
+    if let Some(first) = tail_first_newline {
+        inner_end = first;
+    }
+
+    if inner_end > inner_start {
+        texts.push(text[inner_start..inner_end].to_owned());
+    }
+
+    if let (Some(first), Some(last)) = (tail_first_newline, tail_last_newline) {
+        if head_last_newline.is_none_or(|head_last| first > head_last) {
+            texts.push("\n".to_owned());
+
+            if last + 1 < text.len() {
+                texts.push(text[last + 1..].to_owned());
+            }
+        }
+    }
+
+    Some(texts)
+}
+
+/// Pick a story seed from the current time, kept small like the reference runtime does.
+fn initial_story_seed() -> i32 {
+    let ticks = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.subsec_nanos()).unwrap_or(0);
+    Prng::new(ticks as i32).next() % 100
+}
+
+/// Collapse runs of inline whitespace into a single space, and remove the inline whitespace at
+/// the start and the end of each line.
+pub fn clean_output_whitespace(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut whitespace_start = None;
+    let mut start_of_line = 0;
+
+    for (i, c) in text.char_indices() {
+        let is_whitespace = is_inline_whitespace(c);
+
+        if is_whitespace && whitespace_start.is_none() {
+            whitespace_start = Some(i);
+        }
+
+        if !is_whitespace {
+            if let Some(start) = whitespace_start {
+                if c != '\n' && start > 0 && start != start_of_line {
+                    cleaned.push(' ');
+                }
+            }
+
+            whitespace_start = None;
+        }
+
+        if c == '\n' {
+            start_of_line = i + 1;
+        }
+
+        if !is_whitespace {
+            cleaned.push(c);
+        }
+    }
+
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_head_tail_whitespace_test() {
+        assert_eq!(split_head_tail_whitespace("Hello"), None);
+        assert_eq!(split_head_tail_whitespace("\n"), Some(vec!["\n".to_owned()]));
+        assert_eq!(split_head_tail_whitespace("Hello\n"), Some(vec!["Hello".to_owned(), "\n".to_owned()]));
+        assert_eq!(split_head_tail_whitespace(" \n\nHello\nworld \n  "),
+                   Some(vec![" ".to_owned(), "\n".to_owned(), "Hello\nworld ".to_owned(), "\n".to_owned(), "  ".to_owned()]));
+    }
+
+    #[test]
+    fn clean_output_whitespace_test() {
+        assert_eq!(clean_output_whitespace("Hello world"), "Hello world");
+        assert_eq!(clean_output_whitespace("  Hello \t  world  \n"), "Hello world\n");
+        assert_eq!(clean_output_whitespace("A \n  B\n"), "A\nB\n");
+    }
+}
@@ -0,0 +1,40 @@
+use runtime::value::Value;
+
+/// Handle of a variable observer, used to remove the observer from the story.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VariableObserverHandle(usize);
+
+pub type VariableObserverCallback = Box<dyn FnMut(&str, &Value)>;
+
+/// Callback called with the name and the new value of a global variable when it changes.
+pub struct VariableObserver {
+    handle: VariableObserverHandle,
+    variable_name: Option<String>,
+    callback: VariableObserverCallback
+}
+
+impl VariableObserver {
+    /// Create an observer of the given variable, or of all the variables if no name is given.
+    pub fn new(handle: usize, variable_name: Option<String>, callback: VariableObserverCallback) -> VariableObserver {
+        VariableObserver {
+            handle: VariableObserverHandle(handle),
+            variable_name: variable_name,
+            callback: callback
+        }
+    }
+
+    pub fn handle(&self) -> VariableObserverHandle {
+        self.handle
+    }
+
+    pub fn observes(&self, variable_name: &str) -> bool {
+        match self.variable_name {
+            Some(ref name) => name == variable_name,
+            _ => true
+        }
+    }
+
+    pub fn notify(&mut self, variable_name: &str, value: &Value) {
+        (self.callback)(variable_name, value)
+    }
+}
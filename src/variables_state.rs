@@ -0,0 +1,218 @@
+use callstack::CallStack;
+use error::InkError;
+use json_parser::read_value;
+use json_writer::write_value;
+use runtime::value::Value;
+use runtime::variable::VariableAssignment;
+
+use serde_json;
+use serde_json::Map;
+
+use std::collections::HashMap;
+
+/// Storage of the global variables. Temporary variables are stored in the callstack elements, so
+/// the callstack is given to the methods which may need to access them.
+///
+/// Variables are looked up with a context index: 0 for the globals, the 1-based index of the
+/// callstack element for temporaries, or -1 to look up from the current callstack element.
+#[derive(Clone)]
+pub struct VariablesState {
+    global_variables: HashMap<String, Value>,
+    default_global_variables: HashMap<String, Value>,
+    changed_variables: Vec<String>
+}
+
+impl VariablesState {
+    pub fn new() -> VariablesState {
+        VariablesState {
+            global_variables: HashMap::new(),
+            default_global_variables: HashMap::new(),
+            changed_variables: Vec::new()
+        }
+    }
+
+    pub fn global_variable(&self, name: &str) -> Option<&Value> {
+        self.global_variables.get(name).or_else(|| self.default_global_variables.get(name))
+    }
+
+    pub fn global_variable_exists_with_name(&self, name: &str) -> bool {
+        self.global_variables.contains_key(name) || self.default_global_variables.contains_key(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let mut value = value;
+        if let Some(old_value) = self.global_variables.get(name) {
+            value.retain_list_origins_for_assignment(old_value);
+        }
+
+        if self.global_variables.get(name) != Some(&value) && !self.changed_variables.iter().any(|changed| changed == name) {
+            self.changed_variables.push(name.to_owned());
+        }
+
+        self.global_variables.insert(name.to_owned(), value);
+    }
+
+    /// Take the names of the globals which have changed since the last call, in the order they
+    /// first changed.
+    pub fn take_changed_variables(&mut self) -> Vec<String> {
+        ::std::mem::take(&mut self.changed_variables)
+    }
+
+    /// Keep the values of the globals as declared by the story, they are used as a fallback when
+    /// a global is missing, for instance from an older save.
+    pub fn snapshot_default_globals(&mut self) {
+        self.default_global_variables = self.global_variables.clone();
+        self.changed_variables.clear();
+    }
+
+    /// Write the globals in the JSON format of the story state. The globals which still have the
+    /// value declared by the story are not written.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = Map::new();
+
+        for (name, value) in &self.global_variables {
+            if self.default_global_variables.get(name) != Some(value) {
+                json.insert(name.clone(), write_value(value));
+            }
+        }
+
+        serde_json::Value::Object(json)
+    }
+
+    /// Read the globals written by `to_json`, the globals missing from the JSON get the value
+    /// declared by the story.
+    pub fn load_json(&mut self, json: &serde_json::Value) -> Result<(), InkError> {
+        let mut global_variables = self.default_global_variables.clone();
+
+        match json.as_object() {
+            Some(variables) => for (name, value) in variables {
+                global_variables.insert(name.clone(), read_value(value)?);
+            },
+            _ => return Err(InkError::from_message("Expected an object for the variables in the story state"))
+        }
+
+        self.global_variables = global_variables;
+        self.changed_variables.clear();
+        Ok(())
+    }
+
+    /// Get the value of a variable, following variable pointers.
+    pub fn variable(&self, name: &str, context_index: i32, callstack: &CallStack) -> Option<Value> {
+        match self.raw_variable(name, context_index, callstack) {
+            Some(Value::VariablePointer(ref name, context_index)) => self.variable(name, context_index, callstack),
+            value => value
+        }
+    }
+
+    /// Get the value of a variable without following variable pointers.
+    pub fn raw_variable(&self, name: &str, context_index: i32, callstack: &CallStack) -> Option<Value> {
+        if context_index == 0 || context_index == -1 {
+            if let Some(value) = self.global_variable(name) {
+                return Some(value.clone());
+            }
+        }
+
+        callstack.temporary_variable(name, context_index).cloned()
+    }
+
+    /// Assign a value to a global or temporary variable. Assigning to an existing variable which
+    /// holds a variable pointer (a `ref` parameter) assigns to the variable it points to.
+    pub fn assign(&mut self, variable_assignment: &VariableAssignment, value: Value, callstack: &mut CallStack) -> Result<(), InkError> {
+        let mut name = variable_assignment.name().to_owned();
+        let mut context_index = -1;
+        let mut value = value;
+        let mut set_global = if variable_assignment.is_new_declaration() {
+            variable_assignment.is_global()
+        } else {
+            self.global_variable_exists_with_name(&name)
+        };
+
+        if variable_assignment.is_new_declaration() {
+            // Constructing a new variable pointer reference
+            if let Value::VariablePointer(ref pointer_name, pointer_context_index) = value.clone() {
+                value = self.resolve_variable_pointer(pointer_name, pointer_context_index, callstack);
+            }
+        } else {
+            // Assigning to an existing variable pointer, assign to the variable it points to
+            while let Some(Value::VariablePointer(pointer_name, pointer_context_index)) = self.raw_variable(&name, context_index, callstack) {
+                name = pointer_name;
+                context_index = pointer_context_index;
+                set_global = context_index == 0;
+            }
+        }
+
+        if set_global {
+            self.set_global(&name, value);
+            Ok(())
+        } else {
+            callstack.set_temporary_variable(&name, value, variable_assignment.is_new_declaration(), context_index)
+        }
+    }
+
+    /// Resolve the context index of a variable pointer. A pointer to a variable which is itself a
+    /// pointer resolves to the final pointer, so that pointers never chain.
+    fn resolve_variable_pointer(&self, name: &str, context_index: i32, callstack: &CallStack) -> Value {
+        let context_index = if context_index == -1 {
+            if self.global_variable_exists_with_name(name) { 0 } else { callstack.depth() as i32 }
+        } else {
+            context_index
+        };
+
+        match self.raw_variable(name, context_index, callstack) {
+            Some(pointer @ Value::VariablePointer(_, _)) => pointer,
+            _ => Value::VariablePointer(name.to_owned(), context_index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::container::Container;
+
+    use std::rc::Rc;
+
+    fn callstack() -> CallStack {
+        CallStack::new(&Rc::new(Container::new()))
+    }
+
+    #[test]
+    fn global_and_temporary_variables_test() {
+        let mut callstack = callstack();
+        let mut variables_state = VariablesState::new();
+
+        variables_state.assign(&VariableAssignment::new("gold".to_owned(), true, true), Value::Int(5), &mut callstack).unwrap();
+        variables_state.assign(&VariableAssignment::new("x".to_owned(), true, false), Value::Int(2), &mut callstack).unwrap();
+        variables_state.assign(&VariableAssignment::new("gold".to_owned(), false, false), Value::Int(6), &mut callstack).unwrap();
+
+        assert_eq!(variables_state.global_variable("gold"), Some(&Value::Int(6)));
+        assert_eq!(variables_state.variable("x", -1, &callstack), Some(Value::Int(2)));
+        assert_eq!(variables_state.variable("x", 0, &callstack), None);
+        assert_eq!(callstack.context_for_variable_named("x"), 1);
+        assert_eq!(callstack.context_for_variable_named("gold"), 0);
+
+        // Temporaries must be declared before being assigned
+        assert!(variables_state.assign(&VariableAssignment::new("y".to_owned(), false, false), Value::Int(1), &mut callstack).is_err());
+    }
+
+    #[test]
+    fn variable_pointer_test() {
+        let mut callstack = callstack();
+        let mut variables_state = VariablesState::new();
+
+        variables_state.assign(&VariableAssignment::new("gold".to_owned(), true, true), Value::Int(5), &mut callstack).unwrap();
+
+        // A ref parameter pointing to the global, then a pointer to that parameter
+        let pointer = Value::VariablePointer("gold".to_owned(), -1);
+        variables_state.assign(&VariableAssignment::new("x".to_owned(), true, false), pointer, &mut callstack).unwrap();
+        let pointer = Value::VariablePointer("x".to_owned(), 1);
+        variables_state.assign(&VariableAssignment::new("y".to_owned(), true, false), pointer, &mut callstack).unwrap();
+
+        assert_eq!(variables_state.raw_variable("x", -1, &callstack), Some(Value::VariablePointer("gold".to_owned(), 0)));
+        assert_eq!(variables_state.raw_variable("y", -1, &callstack), Some(Value::VariablePointer("gold".to_owned(), 0)));
+
+        variables_state.assign(&VariableAssignment::new("y".to_owned(), false, false), Value::Int(7), &mut callstack).unwrap();
+        assert_eq!(variables_state.global_variable("gold"), Some(&Value::Int(7)));
+        assert_eq!(variables_state.variable("x", -1, &callstack), Some(Value::Int(7)));
+    }
+}